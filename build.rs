@@ -0,0 +1,17 @@
+use std::env;
+
+fn main() {
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = env::var("OUT_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=CALCU_UPDATE_HEADER");
+
+    // the header of the C interface in src/ffi.rs, the copy in include/ is
+    // only rewritten when asked
+    let bindings = cbindgen::generate(&crate_dir).expect("unable to generate the C header");
+    bindings.write_to_file(format!("{}/cal_cu_lator.h", out_dir));
+    if env::var_os("CALCU_UPDATE_HEADER").is_some() {
+        bindings.write_to_file(format!("{}/include/cal_cu_lator.h", crate_dir));
+    }
+}
@@ -0,0 +1,244 @@
+#ifndef CAL_CU_LATOR_H
+#define CAL_CU_LATOR_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Outcome of a call, one value for each kind of error.
+ */
+typedef enum CalcuStatus {
+  CALCU_STATUS_OK = 0,
+  /**
+   * A null pointer, an index out of range or text that is not UTF-8.
+   */
+  CALCU_STATUS_INVALID_ARGUMENT = 1,
+  CALCU_STATUS_IO = 2,
+  CALCU_STATUS_PARSE = 3,
+  CALCU_STATUS_TOO_MANY_FIELDS = 4,
+  CALCU_STATUS_INVALID_GOAL = 5,
+  CALCU_STATUS_EMPTY_INPUT = 6,
+  CALCU_STATUS_INVALID_OPTION = 7,
+  CALCU_STATUS_CANCELLED = 8,
+} CalcuStatus;
+
+/**
+ * What a run prints on stderr as it goes.
+ */
+typedef enum CalcuProgress {
+  /**
+   * Nothing, the default.
+   */
+  CALCU_PROGRESS_QUIET = 0,
+  /**
+   * Percentage, rate and time left, for people.
+   */
+  CALCU_PROGRESS_TEXT = 1,
+  /**
+   * One JSON object per line, for programs.
+   */
+  CALCU_PROGRESS_JSON = 2,
+} CalcuProgress;
+
+/**
+ * The ranked formulas of a run.
+ */
+typedef struct CalcuResults CalcuResults;
+
+/**
+ * Items and search settings, built up call by call.
+ */
+typedef struct CalcuSolver CalcuSolver;
+
+/**
+ * One ranked formula.
+ */
+typedef struct CalcuResult {
+  /**
+   * Distance from the goal.
+   */
+  double diff;
+  /**
+   * Total of the formula minus the goal.
+   */
+  double error;
+  /**
+   * Number of fields the formula uses.
+   */
+  size_t num_selected;
+} CalcuResult;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Message of the last failed call on this thread, NULL if none failed.
+ * The text stays valid until the next failing call on the same thread.
+ */
+const char *calcu_last_error(void);
+
+/**
+ * A quiet solver with no items, a goal of 0 and a rank size of 10.
+ */
+struct CalcuSolver *calcu_solver_new(void);
+
+/**
+ * # Safety
+ *
+ * `solver` must come from `calcu_solver_new` and not be used afterwards,
+ * or be NULL.
+ */
+void calcu_solver_free(struct CalcuSolver *solver);
+
+/**
+ * Adds an item named `name` with `len` values.
+ *
+ * # Safety
+ *
+ * `solver` must be a live solver, `name` a NUL-terminated string and
+ * `values` point to `len` doubles.
+ */
+enum CalcuStatus calcu_solver_add_item(struct CalcuSolver *solver,
+                                       const char *name,
+                                       const double *values,
+                                       size_t len);
+
+/**
+ * Adds the items of a CSV export held in `len` bytes, read with the
+ * default options: guessed delimiter and layout, English numbers and an
+ * error on blank cells.
+ *
+ * # Safety
+ *
+ * `solver` must be a live solver and `data` point to `len` bytes.
+ */
+enum CalcuStatus calcu_solver_load_csv(struct CalcuSolver *solver, const uint8_t *data, size_t len);
+
+/**
+ * Number of items added so far.
+ *
+ * # Safety
+ *
+ * `solver` must be a live solver or NULL.
+ */
+size_t calcu_solver_num_items(const struct CalcuSolver *solver);
+
+/**
+ * # Safety
+ *
+ * `solver` must be a live solver.
+ */
+enum CalcuStatus calcu_solver_set_goal(struct CalcuSolver *solver, double goal);
+
+/**
+ * # Safety
+ *
+ * `solver` must be a live solver.
+ */
+enum CalcuStatus calcu_solver_set_rank_size(struct CalcuSolver *solver, size_t rank_size);
+
+/**
+ * Prints the progress of runs, and notices about them, on stderr as
+ * `progress` says.
+ *
+ * # Safety
+ *
+ * `solver` must be a live solver.
+ */
+enum CalcuStatus calcu_solver_set_progress(struct CalcuSolver *solver, enum CalcuProgress progress);
+
+/**
+ * Makes every formula use item `index`.
+ *
+ * # Safety
+ *
+ * `solver` must be a live solver.
+ */
+enum CalcuStatus calcu_solver_pin(struct CalcuSolver *solver, size_t index);
+
+/**
+ * Leaves item `index` out of every formula.
+ *
+ * # Safety
+ *
+ * `solver` must be a live solver.
+ */
+enum CalcuStatus calcu_solver_exclude(struct CalcuSolver *solver, size_t index);
+
+/**
+ * Searches the formulas closest to the goal, storing them in `*results`
+ * on success.
+ *
+ * # Safety
+ *
+ * `solver` must be a live solver and `results` point to writable memory.
+ */
+enum CalcuStatus calcu_solver_run(const struct CalcuSolver *solver, struct CalcuResults **results);
+
+/**
+ * # Safety
+ *
+ * `results` must come from `calcu_solver_run` and not be used afterwards,
+ * or be NULL.
+ */
+void calcu_results_free(struct CalcuResults *results);
+
+/**
+ * Number of formulas, best first.
+ *
+ * # Safety
+ *
+ * `results` must be live results or NULL.
+ */
+size_t calcu_results_len(const struct CalcuResults *results);
+
+/**
+ * Reads formula `rank` (0-based) into `*result`.
+ *
+ * # Safety
+ *
+ * `results` must be live results and `result` point to writable memory.
+ */
+enum CalcuStatus calcu_results_get(const struct CalcuResults *results,
+                                   size_t rank,
+                                   struct CalcuResult *result);
+
+/**
+ * Writes the sign of every solver item in formula `rank`: `1` added, `-1`
+ * subtracted, `0` not used. `len` must be at least the number of items.
+ *
+ * # Safety
+ *
+ * `results` must be live results and `signs` point to `len` writable bytes.
+ */
+enum CalcuStatus calcu_results_signs(const struct CalcuResults *results,
+                                     size_t rank,
+                                     int8_t *signs,
+                                     size_t len);
+
+/**
+ * Writes the indices of the solver items used by formula `rank`, in item
+ * order, up to `len` of them. Returns how many the formula uses, which may
+ * be more than `len`.
+ *
+ * # Safety
+ *
+ * `results` must be live results and `indices` point to `len` writable
+ * values.
+ */
+size_t calcu_results_fields(const struct CalcuResults *results,
+                            size_t rank,
+                            size_t *indices,
+                            size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CAL_CU_LATOR_H */
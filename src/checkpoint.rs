@@ -0,0 +1,133 @@
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::item::Item;
+use crate::singleresult::SingleResult;
+use crate::sorted_vec::SortedVec;
+
+/// Where and how often searches save their progress.
+#[derive(Debug)]
+pub(crate) struct Checkpointing {
+    pub dir: PathBuf,
+    pub interval: Duration,
+}
+
+/// Progress of a search: every selection below `next_select` is done and
+/// `results` holds the best formulas among them.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<R> {
+    fingerprint: String,
+    next_select: u32,
+    results: R,
+}
+
+impl Checkpointing {
+    /// Each search has its own file, named after its fingerprint.
+    fn path(&self, fingerprint: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", fingerprint))
+    }
+
+    /// The saved progress of the search with `fingerprint`, if any.
+    pub fn load(
+        &self,
+        fingerprint: u64,
+        field_names: &[String],
+    ) -> Result<Option<(u32, SortedVec<SingleResult>)>, Error> {
+        let path = self.path(fingerprint);
+        let source = path.display().to_string();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::io(&source, e)),
+        };
+        let mut checkpoint: Checkpoint<SortedVec<SingleResult>> =
+            serde_json::from_str(&content).map_err(|e| Error::from_json(&source, &e))?;
+        if checkpoint.fingerprint != format!("{:016x}", fingerprint) {
+            return Err(Error::parse(
+                &source,
+                None,
+                None,
+                "the checkpoint is for another search",
+            ));
+        }
+        for result in checkpoint.results.data.iter_mut() {
+            result.field_names = field_names.to_vec();
+        }
+        Ok(Some((checkpoint.next_select, checkpoint.results)))
+    }
+
+    /// Saves the progress, replacing the previous checkpoint only once the
+    /// new one is written.
+    pub fn save(
+        &self,
+        fingerprint: u64,
+        next_select: u32,
+        results: &SortedVec<SingleResult>,
+    ) -> Result<(), Error> {
+        let path = self.path(fingerprint);
+        let source = path.display().to_string();
+        let checkpoint = Checkpoint {
+            fingerprint: format!("{:016x}", fingerprint),
+            next_select,
+            results,
+        };
+        let json = serde_json::to_string(&checkpoint).map_err(|e| Error::io(&source, e.into()))?;
+        let temporary = path.with_extension("json.tmp");
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&temporary, json))
+            .and_then(|_| fs::rename(&temporary, &path))
+            .map_err(|e| Error::io(&source, e))
+    }
+
+    /// Drops the checkpoint of a finished search.
+    pub fn remove(&self, fingerprint: u64) -> Result<(), Error> {
+        let path = self.path(fingerprint);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(Error::io(&path.display().to_string(), e))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Identifies a search by everything its results depend on, down to the
+/// `selections` it searches. FNV-1a, so it is the same from one build to the
+/// next.
+pub(crate) fn fingerprint(
+    fields: &[Item],
+    rank_size: usize,
+    pinned_mask: u32,
+    metric: &str,
+    goals: &[f64],
+    selections: Range<u32>,
+) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    for field in fields {
+        write(field.name.as_bytes());
+        write(&[0]);
+        for value in &field.values {
+            write(&value.to_bits().to_le_bytes());
+        }
+    }
+    write(&(rank_size as u64).to_le_bytes());
+    write(&pinned_mask.to_le_bytes());
+    write(metric.as_bytes());
+    for goal in goals {
+        write(&goal.to_bits().to_le_bytes());
+    }
+    write(&selections.start.to_le_bytes());
+    write(&selections.end.to_le_bytes());
+    hash
+}
@@ -0,0 +1,328 @@
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+
+use cal_cu_lator::constraints::Constraints;
+use cal_cu_lator::loader::{self, Layout, LoaderOptions, MissingValues};
+use cal_cu_lator::numberformat::NumberFormat;
+use cal_cu_lator::output::OutputFormat;
+use cal_cu_lator::run::{Job, RunOptions};
+use cal_cu_lator::shard::Shard;
+use cal_cu_lator::solver::DEFAULT_RANK_SIZE;
+use cal_cu_lator::{Error, ProgressOutput, SearchControl};
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0    success
+  1    verify: the formula doesn't reach the goal
+  2    invalid arguments or options
+  3    a file could not be read or written
+  4    a file could not be parsed
+  5    too many fields to search
+  6    invalid goal
+  7    nothing to search
+  130  cancelled";
+
+// flags a job file sets itself, they would be ignored next to it
+const JOB_FILE_SETTINGS: [&str; 19] = [
+    "inputs",
+    "format",
+    "output",
+    "combined_rank_size",
+    "locale",
+    "delimiter",
+    "layout",
+    "missing",
+    "holdout",
+    "equivalence_tolerance",
+    "keep_zero_rows",
+    "prune_redundant",
+    "pin",
+    "exclude",
+    "time_limit",
+    "max_evaluations",
+    "checkpoint_dir",
+    "checkpoint_interval",
+    "progress",
+];
+
+/// Finds the signed sums of payslip fields closest to a goal.
+#[derive(Debug, Parser)]
+#[command(name = "cal-cu-lator", version, after_help = EXIT_CODES)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Rank the formulas closest to the goal of each file, combining the
+    /// rankings when there are several files.
+    Solve(SolveArgs),
+    /// Like solve, but only write the ranking combined across files.
+    Combine(SolveArgs),
+    /// Compute the total of a formula and how far it is from the goal.
+    Verify(VerifyArgs),
+    /// Show the periods, fields and blank cells read from a file.
+    Inspect(InspectArgs),
+    /// Answer newline-delimited JSON requests from stdin on stdout until
+    /// stdin is closed.
+    Serve,
+    /// Load a file once and search it interactively, changing the goal and
+    /// the constraints between searches.
+    Repl(ReplArgs),
+    /// Serve the HTTP API on localhost.
+    Http(HttpArgs),
+    /// Merge the partial rankings written by `solve --shard` into the
+    /// rankings of the whole search.
+    Merge(MergeArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+    /// Write results to this file instead of stdout.
+    #[arg(long, value_name = "PATH")]
+    pub output: Option<String>,
+    /// Results kept in the combined ranking.
+    #[arg(long, default_value_t = DEFAULT_RANK_SIZE)]
+    pub combined_rank_size: usize,
+    /// Partial rankings, one file per shard.
+    #[arg(required = true)]
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct HttpArgs {
+    #[arg(long, default_value_t = 8080)]
+    pub port: u16,
+    /// Searches run at the same time, the others wait in line.
+    #[arg(long, default_value_t = 2)]
+    pub max_jobs: usize,
+    /// Finished jobs kept for their results, older ones are forgotten as
+    /// new jobs come in.
+    #[arg(long, default_value_t = 100)]
+    pub keep_jobs: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct SolveArgs {
+    /// Read files, goals and options from a TOML or JSON job file, which
+    /// then can't be given on the command line too.
+    #[arg(long, value_name = "PATH", conflicts_with_all = JOB_FILE_SETTINGS)]
+    pub job: Option<String>,
+    #[command(flatten)]
+    pub loader: LoaderArgs,
+    #[command(flatten)]
+    pub solver: SolverArgs,
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+    /// Write results to this file instead of stdout.
+    #[arg(long, value_name = "PATH")]
+    pub output: Option<String>,
+    /// Results kept in the combined ranking.
+    #[arg(long, default_value_t = DEFAULT_RANK_SIZE)]
+    pub combined_rank_size: usize,
+    /// Only search slice i of N, e.g. 2/4, and write the partial rankings
+    /// as JSON for `merge` instead of the results.
+    #[arg(long, value_name = "i/N")]
+    pub shard: Option<Shard>,
+    /// `file goal rank_size` triples, `-` reads the file from stdin. Goals
+    /// are comma separated per-period lists with --holdout, put `--` before
+    /// the triples when a list starts with a negative goal.
+    #[arg(
+        value_name = "FILE GOAL RANK_SIZE",
+        required_unless_present = "job",
+        allow_negative_numbers = true
+    )]
+    pub inputs: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    #[command(flatten)]
+    pub loader: LoaderArgs,
+    /// Largest difference from the goal that still counts as a match.
+    #[arg(long, default_value_t = 0.005)]
+    pub tolerance: f64,
+    /// File to read, `-` for stdin.
+    pub file: String,
+    #[arg(allow_negative_numbers = true)]
+    pub goal: f64,
+    /// Signed fields as in the pretty formula, e.g. "+ AAAAA - BBBBB".
+    #[arg(allow_hyphen_values = true)]
+    pub formula: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ReplArgs {
+    #[command(flatten)]
+    pub loader: LoaderArgs,
+    #[command(flatten)]
+    pub solver: SolverArgs,
+    #[arg(long, default_value_t = DEFAULT_RANK_SIZE)]
+    pub rank_size: usize,
+    /// File to load on start.
+    pub file: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    #[command(flatten)]
+    pub loader: LoaderArgs,
+    /// File to read, `-` for stdin.
+    pub file: String,
+}
+
+#[derive(Debug, Args)]
+pub struct LoaderArgs {
+    /// Number format of the export: en, it, de, es, nl, pt, fr or ch. A
+    /// decimal comma is assumed for `;` delimited exports, en otherwise.
+    #[arg(long, value_parser = parse_locale)]
+    pub locale: Option<NumberFormat>,
+    /// Field delimiter, guessed when not given; `tab` or `\t` for tabs.
+    #[arg(long, value_parser = parse_delimiter)]
+    pub delimiter: Option<u8>,
+    #[arg(long, value_enum, default_value_t)]
+    pub layout: Layout,
+    /// What to do with blank cells.
+    #[arg(long, value_enum, default_value_t)]
+    pub missing: MissingValues,
+}
+
+#[derive(Debug, Args)]
+pub struct SolverArgs {
+    /// Periods held out for cross-validation, by name or 1-based position.
+    #[arg(long, value_delimiter = ',', value_name = "PERIODS")]
+    pub holdout: Option<Vec<String>>,
+    /// Collapse fields whose values match within this tolerance.
+    #[arg(long, value_name = "TOLERANCE")]
+    pub equivalence_tolerance: Option<f64>,
+    /// Search all-zero fields too instead of pruning them.
+    #[arg(long)]
+    pub keep_zero_rows: bool,
+    /// Prune fields that are a `+1`/`-1` sum of other fields, multiples of
+    /// fields are not detected.
+    #[arg(long)]
+    pub prune_redundant: bool,
+    /// Fields every formula must use.
+    #[arg(long, value_delimiter = ',', value_name = "FIELDS")]
+    pub pin: Vec<String>,
+    /// Fields no formula may use.
+    #[arg(long, value_delimiter = ',', value_name = "FIELDS")]
+    pub exclude: Vec<String>,
+    /// Stop each search after this many seconds with the best formulas
+    /// found so far.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    pub time_limit: Option<Duration>,
+    /// Stop each search after evaluating about this many formulas.
+    #[arg(long, value_name = "COUNT")]
+    pub max_evaluations: Option<u64>,
+    /// Save the progress of each search in this directory, and resume the
+    /// searches saved there.
+    #[arg(long, value_name = "DIR")]
+    pub checkpoint_dir: Option<String>,
+    /// Seconds between two checkpoints.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, default_value = "60")]
+    pub checkpoint_interval: Duration,
+    /// How progress and notices are printed on stderr.
+    #[arg(long, value_enum, default_value_t = ProgressOutput::Text)]
+    pub progress: ProgressOutput,
+}
+
+fn parse_locale(locale: &str) -> Result<NumberFormat, String> {
+    NumberFormat::from_locale(locale).ok_or_else(|| format!("unknown locale {}", locale))
+}
+
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    seconds
+        .parse()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| "expected a number of seconds".to_string())
+}
+
+fn parse_delimiter(delimiter: &str) -> Result<u8, String> {
+    loader::parse_delimiter(delimiter)
+        .ok_or_else(|| "expected a single character or `tab`".to_string())
+}
+
+impl LoaderArgs {
+    pub fn options(&self) -> LoaderOptions {
+        LoaderOptions {
+            delimiter: self.delimiter,
+            number_format: self.locale,
+            layout: self.layout,
+            missing_values: self.missing,
+        }
+    }
+}
+
+impl SolverArgs {
+    pub fn run_options(&self, loader: LoaderOptions) -> RunOptions {
+        let mut control = SearchControl::new().progress_output(self.progress);
+        if let Some(limit) = self.time_limit {
+            control = control.time_limit(limit);
+        }
+        if let Some(limit) = self.max_evaluations {
+            control = control.evaluation_limit(limit);
+        }
+        if let Some(dir) = &self.checkpoint_dir {
+            control = control.checkpoint(dir, self.checkpoint_interval);
+        }
+        RunOptions {
+            loader,
+            holdout: self.holdout.clone(),
+            equivalence_tolerance: self.equivalence_tolerance,
+            keep_zero_rows: self.keep_zero_rows,
+            prune_redundant: self.prune_redundant,
+            constraints: Constraints {
+                pinned: self.pin.clone(),
+                excluded: self.exclude.clone(),
+            },
+            control,
+        }
+    }
+}
+
+impl SolveArgs {
+    /// Builds the jobs and their options from the flags and the
+    /// `file goal rank_size` triples.
+    pub fn jobs(&self) -> Result<(Vec<Job>, RunOptions), Error> {
+        let options = self.solver.run_options(self.loader.options());
+
+        if !self.inputs.len().is_multiple_of(3) {
+            return Err(Error::InvalidOption(format!(
+                "expected FILE GOAL RANK_SIZE triples, got {} arguments",
+                self.inputs.len()
+            )));
+        }
+
+        let mut jobs = Vec::new();
+        for triple in self.inputs.chunks(3) {
+            let file = triple[0].clone();
+            let goals: Vec<f64> = triple[1]
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| Error::InvalidGoal(format!("{}: invalid goal {}", file, triple[1])))?;
+            if options.holdout.is_none() && goals.len() != 1 {
+                return Err(Error::InvalidGoal(format!(
+                    "{}: per-period goals need --holdout",
+                    file
+                )));
+            }
+            let rank_size: usize = triple[2].parse().map_err(|_| {
+                Error::InvalidOption(format!("{}: invalid rank size {}", file, triple[2]))
+            })?;
+            jobs.push(Job {
+                file,
+                goals,
+                rank_size,
+            });
+        }
+
+        Ok((jobs, options))
+    }
+}
@@ -1,6 +1,10 @@
 use crate::permutation::Permutation;
+use crate::ranking::Ranking;
+use crate::singleresult::SingleResult;
+use crate::sorted_vec::SortedVec;
 use std::cmp::Ordering;
-use std::default;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fmt::Display;
 use utils::avg;
 
@@ -96,3 +100,49 @@ impl Ord for CombinedResult {
         self.perm_cmp(other)
     }
 }
+
+/// The fields a formula adds and subtracts, by name: pruned or collapsed
+/// fields make the same bit stand for different fields in different files.
+fn named_formula(candidate: &SingleResult) -> Vec<(String, i8)> {
+    candidate
+        .field_names
+        .iter()
+        .zip(candidate.get_signs())
+        .filter(|(_, sign)| *sign != 0)
+        .map(|(name, sign)| (name.clone(), sign))
+        .collect()
+}
+
+/// Ranks the formulas found for several files by their average diff, a
+/// formula missing from a ranking is averaged over the others only.
+pub fn combine(rankings: &[Ranking], rank_size: usize) -> SortedVec<CombinedResult> {
+    let mut combined_results: HashMap<Vec<(String, i8)>, CombinedResult> = HashMap::new();
+
+    for ranking in rankings {
+        let res = &ranking.results;
+        // join results into combined results
+        for candidate in &res.data {
+            match combined_results.entry(named_formula(candidate)) {
+                Entry::Occupied(mut entry) => {
+                    entry.get_mut().push_diff(candidate.diff);
+                }
+                Entry::Vacant(entry) => {
+                    let mut comb_res = CombinedResult::new(
+                        candidate.field_names.clone(),
+                        candidate.permutation_sign,
+                        candidate.permutation_select,
+                    );
+                    comb_res.push_diff(candidate.diff);
+                    entry.insert(comb_res);
+                }
+            }
+        }
+    }
+
+    // sort the combined results
+    let mut sorted_combined_results: SortedVec<CombinedResult> = SortedVec::new(rank_size);
+    for cr in combined_results.into_values() {
+        sorted_combined_results.insert_ordered(cr);
+    }
+    sorted_combined_results
+}
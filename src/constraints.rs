@@ -0,0 +1,52 @@
+use serde::Deserialize;
+
+use crate::equivalence;
+use crate::error::Error;
+use crate::item::Item;
+
+/// Fields a formula must or must not use, by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Constraints {
+    /// Fields every formula must use.
+    pub pinned: Vec<String>,
+    /// Fields no formula may use.
+    pub excluded: Vec<String>,
+}
+
+impl Constraints {
+    /// Drops the excluded fields, failing on names that are not in `fields`.
+    pub fn exclude(&self, fields: Vec<Item>) -> Result<Vec<Item>, Error> {
+        if let Some(name) = self
+            .excluded
+            .iter()
+            .find(|name| !fields.iter().any(|f| &f.name == *name))
+        {
+            return Err(Error::InvalidOption(format!(
+                "excluded field {} not found",
+                name
+            )));
+        }
+
+        Ok(fields
+            .into_iter()
+            .filter(|f| !self.excluded.contains(&f.name))
+            .collect())
+    }
+
+    /// Mask of the pinned fields, a collapsed field is pinned when any of
+    /// its members is.
+    pub fn pinned_mask(&self, fields: &[Item]) -> Result<u32, Error> {
+        let mut mask = 0_u32;
+        for name in &self.pinned {
+            let position = fields
+                .iter()
+                .position(|f| f.name.split(equivalence::SEPARATOR).any(|n| n == name))
+                .ok_or_else(|| {
+                    Error::InvalidOption(format!("pinned field {} not found or pruned", name))
+                })?;
+            mask |= 1 << position;
+        }
+        Ok(mask)
+    }
+}
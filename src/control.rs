@@ -0,0 +1,346 @@
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::checkpoint::Checkpointing;
+use crate::shard::Shard;
+use crate::singleresult::SingleResult;
+
+/// Formulas evaluated between two chances to save a checkpoint, a few
+/// seconds of work for a single thread.
+pub const DEFAULT_CHECKPOINT_CHUNK: u64 = 1 << 22;
+
+/// Called with the percentage of a search done so far.
+pub type ProgressFn = dyn Fn(u32) + Send + Sync;
+
+/// Called with each event of a search, from the threads running it.
+pub type EventFn = dyn Fn(SearchEvent) + Send + Sync;
+
+/// How far a search is, counted in formulas evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressReport {
+    pub percent: u32,
+    pub evaluated: u64,
+    pub total: u64,
+    /// Formulas evaluated per second since the search started.
+    pub rate: f64,
+    /// Time left at that rate, unknown until the first formulas are done.
+    pub eta: Option<Duration>,
+}
+
+/// How progress and notices are printed on stderr when nobody listens to
+/// them, nothing is printed unless asked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressOutput {
+    /// Percentage, rate and time left, for people.
+    Text,
+    /// One JSON object per line, for programs.
+    Json,
+    /// Nothing at all.
+    #[default]
+    None,
+}
+
+/// What a running search reports as it goes.
+#[derive(Debug, Clone)]
+pub enum SearchEvent {
+    Progress(ProgressReport),
+    /// The best formulas found so far, best first, sent each time they
+    /// change. They are ranked by training diff and carry no validation
+    /// error in cross-validation searches.
+    Results(Vec<SingleResult>),
+    /// Something people may want to know about the search, such as the
+    /// fields pruned before it or the checkpoint it resumes from.
+    Notice(String),
+}
+
+/// Follows and stops a running search, clones share the same search.
+///
+/// A search can be given a budget, in time or in formulas evaluated, past
+/// which it stops and returns the best formulas found so far as partial.
+#[derive(Clone, Default)]
+pub struct SearchControl {
+    cancelled: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+    progress: Option<Arc<ProgressFn>>,
+    events: Option<Arc<EventFn>>,
+    progress_output: ProgressOutput,
+    time_limit: Option<Duration>,
+    evaluation_limit: Option<u64>,
+    checkpoint: Option<Arc<Checkpointing>>,
+    checkpoint_chunk: Option<u64>,
+    shard: Option<Shard>,
+    spent: Arc<Spent>,
+}
+
+/// What a search has used of its budget.
+struct Spent {
+    started: Instant,
+    evaluations: AtomicU64,
+    exhausted: AtomicBool,
+}
+
+impl Default for Spent {
+    fn default() -> Self {
+        Spent {
+            started: Instant::now(),
+            evaluations: AtomicU64::new(0),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+}
+
+impl SearchControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports progress to `report` instead of printing it, or notices, on
+    /// stderr.
+    pub fn on_progress(mut self, report: impl Fn(u32) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(report));
+        self
+    }
+
+    /// Reports progress, notices and every improvement of the best formulas
+    /// to `listen` as the search goes, instead of printing them on stderr.
+    pub fn on_event(mut self, listen: impl Fn(SearchEvent) + Send + Sync + 'static) -> Self {
+        self.events = Some(Arc::new(listen));
+        self
+    }
+
+    /// Like [`on_event`](Self::on_event), sending the events down a
+    /// channel. Events are dropped once the receiver is gone.
+    pub fn send_events(self, sender: Sender<SearchEvent>) -> Self {
+        self.on_event(move |event| {
+            let _ = sender.send(event);
+        })
+    }
+
+    /// Prints progress and notices on stderr as `output` says when neither
+    /// [`on_progress`](Self::on_progress) nor [`on_event`](Self::on_event)
+    /// listen to them.
+    pub fn progress_output(mut self, output: ProgressOutput) -> Self {
+        self.progress_output = output;
+        self
+    }
+
+    /// Stops each search once it has run for `limit`.
+    pub fn time_limit(mut self, limit: Duration) -> Self {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    /// Stops each search once it has evaluated about `limit` formulas, they
+    /// are counted in batches of a few thousands.
+    pub fn evaluation_limit(mut self, limit: u64) -> Self {
+        self.evaluation_limit = Some(limit);
+        self
+    }
+
+    /// Saves the progress of each search in `dir` every `interval`, and when
+    /// it stops early, so that running it again resumes where it was. The
+    /// checkpoint of a search is removed once it is done.
+    pub fn checkpoint(mut self, dir: impl Into<PathBuf>, interval: Duration) -> Self {
+        self.checkpoint = Some(Arc::new(Checkpointing {
+            dir: dir.into(),
+            interval,
+        }));
+        self
+    }
+
+    /// Formulas evaluated between two chances to save a checkpoint, about
+    /// [`DEFAULT_CHECKPOINT_CHUNK`] unless set. Checkpoints are saved less
+    /// often than their interval when a chunk takes longer.
+    pub fn checkpoint_chunk(mut self, evaluations: u64) -> Self {
+        self.checkpoint_chunk = Some(evaluations.max(1));
+        self
+    }
+
+    /// Searches only the slice `shard` of the formulas of each search, the
+    /// slices of a search take about as long as each other. Their rankings
+    /// are put together by [`crate::shard::merge`].
+    pub fn shard(mut self, shard: Shard) -> Self {
+        self.shard = Some(shard);
+        self
+    }
+
+    /// A control with the same budget and progress report, neither
+    /// cancelled nor stopped, for searches that follow a stopped one.
+    pub fn renewed(&self) -> Self {
+        SearchControl {
+            cancelled: Arc::default(),
+            stopped: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Stops the search as soon as possible, it then fails as cancelled.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Stops the search as soon as possible, it then returns the best
+    /// formulas found so far as partial.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn report(&self, report: &ProgressReport) {
+        if let Some(listen) = &self.events {
+            listen(SearchEvent::Progress(report.clone()));
+        }
+        match &self.progress {
+            Some(progress) => progress(report.percent),
+            None if self.events.is_none() => print_progress(self.progress_output, report),
+            None => {}
+        }
+    }
+
+    /// Whether the search has someone to send its results to as it goes.
+    pub(crate) fn is_listened(&self) -> bool {
+        self.events.is_some()
+    }
+
+    pub(crate) fn emit(&self, event: SearchEvent) {
+        if let Some(listen) = &self.events {
+            listen(event);
+        }
+    }
+
+    /// Reports `message` as a notice about the search, like the ones the
+    /// search itself sends.
+    pub fn notice(&self, message: String) {
+        match &self.events {
+            Some(listen) => listen(SearchEvent::Notice(message)),
+            None if self.progress.is_none() => print_notice(self.progress_output, &message),
+            None => {}
+        }
+    }
+
+    /// A clone for a new search, the budget counts from now.
+    pub(crate) fn start(&self) -> Self {
+        SearchControl {
+            spent: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Whether `evaluations` more formulas fit the budget, counting them
+    /// when they do.
+    pub(crate) fn spend(&self, evaluations: u64) -> bool {
+        if self.is_cancelled() || self.stopped_early() {
+            return false;
+        }
+        let over_time = self
+            .time_limit
+            .is_some_and(|limit| self.spent.started.elapsed() >= limit);
+        let over_count = self.evaluation_limit.is_some_and(|limit| {
+            self.spent
+                .evaluations
+                .fetch_add(evaluations, Ordering::Relaxed)
+                .saturating_add(evaluations)
+                > limit
+        });
+        if over_time || over_count {
+            self.spent.exhausted.store(true, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    pub(crate) fn checkpointing(&self) -> Option<&Checkpointing> {
+        self.checkpoint.as_deref()
+    }
+
+    pub(crate) fn chunk_evaluations(&self) -> u64 {
+        self.checkpoint_chunk.unwrap_or(DEFAULT_CHECKPOINT_CHUNK)
+    }
+
+    pub(crate) fn sharding(&self) -> Option<Shard> {
+        self.shard
+    }
+
+    /// Whether the search was stopped, or ran out of budget, before trying
+    /// every formula.
+    pub(crate) fn stopped_early(&self) -> bool {
+        self.is_stopped() || self.spent.exhausted.load(Ordering::Relaxed)
+    }
+}
+
+impl Debug for SearchControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SearchControl")
+            .field("cancelled", &self.is_cancelled())
+            .field("stopped", &self.is_stopped())
+            .field("progress", &self.progress.is_some())
+            .field("events", &self.events.is_some())
+            .field("progress_output", &self.progress_output)
+            .field("time_limit", &self.time_limit)
+            .field("evaluation_limit", &self.evaluation_limit)
+            .field("checkpoint", &self.checkpoint)
+            .field("checkpoint_chunk", &self.checkpoint_chunk)
+            .field("shard", &self.shard)
+            .finish()
+    }
+}
+
+/// Progress goes to stderr, so that results on stdout stay parseable.
+fn print_progress(output: ProgressOutput, report: &ProgressReport) {
+    match output {
+        ProgressOutput::Text => eprintln!(
+            "{}% ({:.0} formulas/s, {} left)",
+            report.percent,
+            report.rate,
+            report.eta.map_or_else(|| "?".to_string(), format_duration)
+        ),
+        ProgressOutput::Json => eprintln!(
+            "{}",
+            serde_json::json!({
+                "type": "progress",
+                "percent": report.percent,
+                "evaluated": report.evaluated,
+                "total": report.total,
+                "rate": report.rate,
+                "eta_seconds": report.eta.map(|eta| eta.as_secs_f64()),
+            })
+        ),
+        ProgressOutput::None => {}
+    }
+}
+
+fn print_notice(output: ProgressOutput, message: &str) {
+    match output {
+        ProgressOutput::Text => eprintln!("{}", message),
+        ProgressOutput::Json => eprintln!(
+            "{}",
+            serde_json::json!({ "type": "notice", "message": message })
+        ),
+        ProgressOutput::None => {}
+    }
+}
+
+/// `1h02m`, `3m05s` or `42s`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, seconds) => format!("{}s", seconds),
+        (0, minutes, seconds) => format!("{}m{:02}s", minutes, seconds),
+        (hours, minutes, _) => format!("{}h{:02}m", hours, minutes),
+    }
+}
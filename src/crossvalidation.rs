@@ -0,0 +1,75 @@
+use crate::control::SearchControl;
+use crate::error::Error;
+use crate::item::Item;
+use crate::singleresult::SingleResult;
+use crate::solver::{get_total_for_perm, search_total};
+use crate::sorted_vec::SortedVec;
+
+/// Fits formulas on the periods that are not held out and validates the
+/// best ones on the held-out periods.
+///
+/// `goals` holds the expected total for each period, `holdout` the 0-based
+/// periods used for validation. Periods where a field has an unknown (`NaN`)
+/// value are left out of both. The rank is ordered by training diff, each
+/// result carries its validation error as well.
+pub fn cross_validate(
+    fields: &[Item],
+    goals: &[f64],
+    holdout: &[usize],
+    rank_size: usize,
+    pinned_mask: u32,
+    control: &SearchControl,
+) -> Result<SortedVec<SingleResult>, Error> {
+    let num_periods = goals.len();
+    if let Some(field) = fields.iter().find(|f| f.values.len() != num_periods) {
+        return Err(Error::InvalidGoal(format!(
+            "field {} has {} periods but {} goals were given",
+            field.name,
+            field.values.len(),
+            num_periods
+        )));
+    }
+    if let Some(period) = holdout.iter().find(|&&p| p >= num_periods) {
+        return Err(Error::InvalidOption(format!(
+            "held-out period {} is out of range (1-{})",
+            period + 1,
+            num_periods
+        )));
+    }
+
+    // periods with unknown values can be neither fitted nor validated
+    let (training, validation): (Vec<usize>, Vec<usize>) = (0..num_periods)
+        .filter(|&p| fields.iter().all(|f| !f.values[p].is_nan()))
+        .partition(|p| !holdout.contains(p));
+    if training.is_empty() || validation.is_empty() {
+        return Err(Error::InvalidOption(
+            "cross-validation needs at least one training and one held-out period".to_string(),
+        ));
+    }
+
+    let training_fields: Vec<Item> = fields.iter().map(|f| f.select_periods(&training)).collect();
+    let training_goal: f64 = training.iter().map(|&p| goals[p]).sum();
+    let validation_fields: Vec<Item> = fields
+        .iter()
+        .map(|f| f.select_periods(&validation))
+        .collect();
+    let validation_goal: f64 = validation.iter().map(|&p| goals[p]).sum();
+
+    let mut rank = search_total(
+        &training_fields,
+        training_goal,
+        rank_size,
+        pinned_mask,
+        control,
+    )?;
+    for result in rank.data.iter_mut() {
+        let validation_total = get_total_for_perm(
+            result.permutation_sign,
+            result.permutation_select,
+            &validation_fields,
+        );
+        result.set_validation_error(validation_total - validation_goal);
+    }
+
+    Ok(rank)
+}
@@ -0,0 +1,72 @@
+use std::fmt::Display;
+
+use crate::formula::SheetRange;
+use crate::item::Item;
+
+/// A blank cell found while loading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingValue {
+    pub field: String,
+    pub period: String,
+    pub line: u64,
+    pub column: usize,
+}
+
+impl Display for MissingValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, {} (line {}, column {})",
+            self.field, self.period, self.line, self.column
+        )
+    }
+}
+
+/// The fields of an export together with the periods their values refer to.
+#[derive(Debug, Clone, Default)]
+pub struct Dataset {
+    /// Period names, from the header when there is one, `1`, `2`, ... otherwise.
+    pub periods: Vec<String>,
+    pub items: Vec<Item>,
+    /// Where the values of each item sit in the input, in the same order.
+    pub ranges: Vec<SheetRange>,
+    /// Blank cells that were filled in or made a field be rejected.
+    pub missing: Vec<MissingValue>,
+    /// Fields left out because of blank cells.
+    pub rejected: Vec<String>,
+}
+
+impl Dataset {
+    /// Finds a period by name, ignoring case, or by its 1-based position.
+    pub fn period_index(&self, period: &str) -> Option<usize> {
+        self.periods
+            .iter()
+            .position(|p| p.eq_ignore_ascii_case(period))
+            .or_else(|| match period.parse::<usize>() {
+                Ok(n) if (1..=self.periods.len()).contains(&n) => Some(n - 1),
+                _ => None,
+            })
+    }
+
+    /// Finds where the values of the first field called `name` sit.
+    pub fn range_of(&self, name: &str) -> Option<&SheetRange> {
+        let position = self.items.iter().position(|i| i.name == name)?;
+        self.ranges.get(position)
+    }
+
+    /// Periods where at least one field has an unknown value.
+    pub fn unknown_periods(&self) -> Vec<usize> {
+        (0..self.periods.len())
+            .filter(|&p| self.items.iter().any(|i| i.values[p].is_nan()))
+            .collect()
+    }
+
+    /// Joins the names of the given periods for reports.
+    pub fn period_names(&self, periods: &[usize]) -> String {
+        periods
+            .iter()
+            .map(|&p| self.periods[p].as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
@@ -0,0 +1,67 @@
+use crate::item::Item;
+
+/// Separates the names of the fields collapsed together.
+pub const SEPARATOR: &str = " | ";
+
+/// Name of the field whose values a collapsed group uses.
+pub fn representative(name: &str) -> &str {
+    name.split(SEPARATOR).next().unwrap_or(name)
+}
+
+/// What two fields must share to be considered interchangeable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Equivalence {
+    /// Same total over all periods, enough when only the annual sum matters.
+    Total,
+    /// Same value in every period.
+    Values,
+}
+
+/// Collapses fields that are equivalent within `tolerance` into a single
+/// search variable named after all of them, e.g. `AAAAA | CCCCC`.
+///
+/// The first field of each group keeps its values and its position. Since
+/// the group becomes one variable, formulas using more than one of its
+/// members are no longer searched.
+pub fn collapse_equivalent(
+    fields: Vec<Item>,
+    tolerance: f64,
+    equivalence: Equivalence,
+) -> Vec<Item> {
+    let mut groups: Vec<(Item, Vec<String>)> = Vec::new();
+
+    for field in fields {
+        let group = groups.iter_mut().find(|(representative, _)| {
+            is_equivalent(representative, &field, tolerance, equivalence)
+        });
+        match group {
+            Some((_, names)) => names.push(field.name),
+            None => {
+                let names = vec![field.name.clone()];
+                groups.push((field, names));
+            }
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(representative, names)| Item {
+            name: names.join(SEPARATOR),
+            values: representative.values,
+        })
+        .collect()
+}
+
+fn is_equivalent(left: &Item, right: &Item, tolerance: f64, equivalence: Equivalence) -> bool {
+    match equivalence {
+        Equivalence::Total => (left.total() - right.total()).abs() <= tolerance,
+        Equivalence::Values => {
+            left.values.len() == right.values.len()
+                && left
+                    .values
+                    .iter()
+                    .zip(&right.values)
+                    .all(|(l, r)| (l - r).abs() <= tolerance)
+        }
+    }
+}
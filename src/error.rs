@@ -0,0 +1,121 @@
+use std::fmt::Display;
+
+/// Everything that can go wrong loading data or searching it.
+#[derive(Debug)]
+pub enum Error {
+    /// A file or stream could not be read or written.
+    Io {
+        source: String,
+        error: std::io::Error,
+    },
+    /// The input is not a valid export, `line` and `column` are 1-based.
+    Parse {
+        source: String,
+        line: Option<u64>,
+        column: Option<usize>,
+        message: String,
+    },
+    /// More fields than a search can hold.
+    TooManyFields { fields: usize, max: usize },
+    /// Goals that are not numbers or don't match the periods.
+    InvalidGoal(String),
+    /// Nothing left to search.
+    EmptyInput(String),
+    /// Options that don't fit the data, such as unknown periods or fields.
+    InvalidOption(String),
+    /// The search was stopped before it was done.
+    Cancelled,
+}
+
+impl Error {
+    pub fn io(source: &str, error: std::io::Error) -> Self {
+        Error::Io {
+            source: source.to_string(),
+            error,
+        }
+    }
+
+    pub fn parse(
+        source: &str,
+        line: impl Into<Option<u64>>,
+        column: impl Into<Option<usize>>,
+        message: impl Into<String>,
+    ) -> Self {
+        Error::Parse {
+            source: source.to_string(),
+            line: line.into(),
+            column: column.into(),
+            message: message.into(),
+        }
+    }
+
+    /// A parse error of `source` from a JSON error, whose message leaves the
+    /// location out as it is reported on its own.
+    pub fn from_json(source: &str, error: &serde_json::Error) -> Self {
+        let message = error.to_string();
+        let message = message.split(" at line ").next().unwrap_or_default();
+        Error::parse(source, error.line() as u64, error.column(), message)
+    }
+
+    /// Short name of the kind of error, for machine readable reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Io { .. } => "io",
+            Error::Parse { .. } => "parse",
+            Error::TooManyFields { .. } => "too_many_fields",
+            Error::InvalidGoal(_) => "invalid_goal",
+            Error::EmptyInput(_) => "empty_input",
+            Error::InvalidOption(_) => "invalid_option",
+            Error::Cancelled => "cancelled",
+        }
+    }
+
+    /// Names `file` in errors that don't point at a source already.
+    pub fn in_file(self, file: &str) -> Self {
+        match self {
+            Error::InvalidGoal(message) => Error::InvalidGoal(format!("{}: {}", file, message)),
+            Error::EmptyInput(message) => Error::EmptyInput(format!("{}: {}", file, message)),
+            Error::InvalidOption(message) => Error::InvalidOption(format!("{}: {}", file, message)),
+            err => err,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io { source, error } => write!(f, "{}: {}", source, error),
+            Error::Parse {
+                source,
+                line,
+                column,
+                message,
+            } => {
+                write!(f, "{}", source)?;
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                }
+                if let Some(column) = column {
+                    write!(f, ":{}", column)?;
+                }
+                write!(f, ": {}", message)
+            }
+            Error::TooManyFields { fields, max } => {
+                write!(f, "too many fields: {} (max {} supported)", fields, max)
+            }
+            Error::InvalidGoal(message)
+            | Error::EmptyInput(message)
+            | Error::InvalidOption(message) => write!(f, "{}", message),
+            Error::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,477 @@
+//! C interface to the solver, see `include/cal_cu_lator.h`.
+//!
+//! A `CalcuSolver` collects items, from CSV buffers or value arrays, and the
+//! goal and constraints to search them with. Running it gives a
+//! `CalcuResults` to read the ranked formulas from. Both are freed with their
+//! own `_free` function. Failing calls return a status other than
+//! `CALCU_STATUS_OK` and leave a message for `calcu_last_error`.
+//!
+//! The header is generated by the build, `CALCU_UPDATE_HEADER=1 cargo build`
+//! writes it to `include/` after a change to this file.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::ptr;
+
+use crate::constraints::Constraints;
+use crate::control::{ProgressOutput, SearchControl};
+use crate::error::Error;
+use crate::item::Item;
+use crate::loader::{self, LoaderOptions};
+use crate::permutation::Permutation;
+use crate::singleresult::SingleResult;
+use crate::solver::{DEFAULT_RANK_SIZE, Solver};
+use crate::sorted_vec::SortedVec;
+
+// source named by errors in buffers
+const BUFFER: &str = "<buffer>";
+
+/// Outcome of a call, one value for each kind of error.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalcuStatus {
+    Ok = 0,
+    /// A null pointer, an index out of range or text that is not UTF-8.
+    InvalidArgument = 1,
+    Io = 2,
+    Parse = 3,
+    TooManyFields = 4,
+    InvalidGoal = 5,
+    EmptyInput = 6,
+    InvalidOption = 7,
+    Cancelled = 8,
+}
+
+/// What a run prints on stderr as it goes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalcuProgress {
+    /// Nothing, the default.
+    Quiet = 0,
+    /// Percentage, rate and time left, for people.
+    Text = 1,
+    /// One JSON object per line, for programs.
+    Json = 2,
+}
+
+/// Items and search settings, built up call by call.
+pub struct CalcuSolver {
+    items: Vec<Item>,
+    goal: f64,
+    rank_size: usize,
+    pinned: Vec<usize>,
+    excluded: Vec<usize>,
+    progress: CalcuProgress,
+}
+
+/// The ranked formulas of a run.
+pub struct CalcuResults {
+    results: SortedVec<SingleResult>,
+    /// Index among the solver items of each searched field.
+    fields: Vec<usize>,
+    num_items: usize,
+}
+
+/// One ranked formula.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CalcuResult {
+    /// Distance from the goal.
+    pub diff: f64,
+    /// Total of the formula minus the goal.
+    pub error: f64,
+    /// Number of fields the formula uses.
+    pub num_selected: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail(status: CalcuStatus, message: String) -> CalcuStatus {
+    // messages come from Display, inner NULs can only come from user text
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    status
+}
+
+fn fail_with(err: Error) -> CalcuStatus {
+    let status = match err {
+        Error::Io { .. } => CalcuStatus::Io,
+        Error::Parse { .. } => CalcuStatus::Parse,
+        Error::TooManyFields { .. } => CalcuStatus::TooManyFields,
+        Error::InvalidGoal(_) => CalcuStatus::InvalidGoal,
+        Error::EmptyInput(_) => CalcuStatus::EmptyInput,
+        Error::InvalidOption(_) => CalcuStatus::InvalidOption,
+        Error::Cancelled => CalcuStatus::Cancelled,
+    };
+    fail(status, err.to_string())
+}
+
+fn invalid_argument(message: &str) -> CalcuStatus {
+    fail(CalcuStatus::InvalidArgument, message.to_string())
+}
+
+/// Message of the last failed call on this thread, NULL if none failed.
+/// The text stays valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn calcu_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// A quiet solver with no items, a goal of 0 and a rank size of 10.
+#[unsafe(no_mangle)]
+pub extern "C" fn calcu_solver_new() -> *mut CalcuSolver {
+    Box::into_raw(Box::new(CalcuSolver {
+        items: Vec::new(),
+        goal: 0_f64,
+        rank_size: DEFAULT_RANK_SIZE,
+        pinned: Vec::new(),
+        excluded: Vec::new(),
+        progress: CalcuProgress::Quiet,
+    }))
+}
+
+/// # Safety
+///
+/// `solver` must come from `calcu_solver_new` and not be used afterwards,
+/// or be NULL.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_solver_free(solver: *mut CalcuSolver) {
+    if !solver.is_null() {
+        drop(unsafe { Box::from_raw(solver) });
+    }
+}
+
+/// Adds an item named `name` with `len` values.
+///
+/// # Safety
+///
+/// `solver` must be a live solver, `name` a NUL-terminated string and
+/// `values` point to `len` doubles.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_solver_add_item(
+    solver: *mut CalcuSolver,
+    name: *const c_char,
+    values: *const f64,
+    len: usize,
+) -> CalcuStatus {
+    let Some(solver) = (unsafe { solver.as_mut() }) else {
+        return invalid_argument("solver is NULL");
+    };
+    if name.is_null() || (values.is_null() && len > 0) {
+        return invalid_argument("name or values is NULL");
+    }
+    let Ok(name) = unsafe { CStr::from_ptr(name) }.to_str() else {
+        return invalid_argument("name is not UTF-8");
+    };
+    let values = if len == 0 {
+        Vec::new()
+    } else {
+        unsafe { std::slice::from_raw_parts(values, len) }.to_vec()
+    };
+    solver.items.push(Item {
+        name: name.to_string(),
+        values,
+    });
+    CalcuStatus::Ok
+}
+
+/// Adds the items of a CSV export held in `len` bytes, read with the
+/// default options: guessed delimiter and layout, English numbers and an
+/// error on blank cells.
+///
+/// # Safety
+///
+/// `solver` must be a live solver and `data` point to `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_solver_load_csv(
+    solver: *mut CalcuSolver,
+    data: *const u8,
+    len: usize,
+) -> CalcuStatus {
+    let Some(solver) = (unsafe { solver.as_mut() }) else {
+        return invalid_argument("solver is NULL");
+    };
+    if data.is_null() && len > 0 {
+        return invalid_argument("data is NULL");
+    }
+    let data = if len == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(data, len) }
+    };
+    match loader::read_items(data, BUFFER, &LoaderOptions::default()) {
+        Ok(dataset) => {
+            solver.items.extend(dataset.items);
+            CalcuStatus::Ok
+        }
+        Err(err) => fail_with(err),
+    }
+}
+
+/// Number of items added so far.
+///
+/// # Safety
+///
+/// `solver` must be a live solver or NULL.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_solver_num_items(solver: *const CalcuSolver) -> usize {
+    unsafe { solver.as_ref() }.map_or(0, |solver| solver.items.len())
+}
+
+/// # Safety
+///
+/// `solver` must be a live solver.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_solver_set_goal(solver: *mut CalcuSolver, goal: f64) -> CalcuStatus {
+    let Some(solver) = (unsafe { solver.as_mut() }) else {
+        return invalid_argument("solver is NULL");
+    };
+    solver.goal = goal;
+    CalcuStatus::Ok
+}
+
+/// # Safety
+///
+/// `solver` must be a live solver.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_solver_set_rank_size(
+    solver: *mut CalcuSolver,
+    rank_size: usize,
+) -> CalcuStatus {
+    let Some(solver) = (unsafe { solver.as_mut() }) else {
+        return invalid_argument("solver is NULL");
+    };
+    solver.rank_size = rank_size;
+    CalcuStatus::Ok
+}
+
+/// Prints the progress of runs, and notices about them, on stderr as
+/// `progress` says.
+///
+/// # Safety
+///
+/// `solver` must be a live solver.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_solver_set_progress(
+    solver: *mut CalcuSolver,
+    progress: CalcuProgress,
+) -> CalcuStatus {
+    let Some(solver) = (unsafe { solver.as_mut() }) else {
+        return invalid_argument("solver is NULL");
+    };
+    solver.progress = progress;
+    CalcuStatus::Ok
+}
+
+/// Makes every formula use item `index`.
+///
+/// # Safety
+///
+/// `solver` must be a live solver.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_solver_pin(solver: *mut CalcuSolver, index: usize) -> CalcuStatus {
+    let Some(solver) = (unsafe { solver.as_mut() }) else {
+        return invalid_argument("solver is NULL");
+    };
+    if index >= solver.items.len() {
+        return invalid_argument("item index out of range");
+    }
+    solver.pinned.push(index);
+    CalcuStatus::Ok
+}
+
+/// Leaves item `index` out of every formula.
+///
+/// # Safety
+///
+/// `solver` must be a live solver.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_solver_exclude(
+    solver: *mut CalcuSolver,
+    index: usize,
+) -> CalcuStatus {
+    let Some(solver) = (unsafe { solver.as_mut() }) else {
+        return invalid_argument("solver is NULL");
+    };
+    if index >= solver.items.len() {
+        return invalid_argument("item index out of range");
+    }
+    solver.excluded.push(index);
+    CalcuStatus::Ok
+}
+
+/// Searches the formulas closest to the goal, storing them in `*results`
+/// on success.
+///
+/// # Safety
+///
+/// `solver` must be a live solver and `results` point to writable memory.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_solver_run(
+    solver: *const CalcuSolver,
+    results: *mut *mut CalcuResults,
+) -> CalcuStatus {
+    let Some(solver) = (unsafe { solver.as_ref() }) else {
+        return invalid_argument("solver is NULL");
+    };
+    if results.is_null() {
+        return invalid_argument("results is NULL");
+    }
+
+    // items may share names, constraints go by position
+    let fields: Vec<usize> = (0..solver.items.len())
+        .filter(|n| !solver.excluded.contains(n))
+        .collect();
+    let items: Vec<Item> = fields
+        .iter()
+        .map(|&n| Item {
+            name: n.to_string(),
+            values: solver.items[n].values.clone(),
+        })
+        .collect();
+    let constraints = Constraints {
+        pinned: solver.pinned.iter().map(usize::to_string).collect(),
+        excluded: Vec::new(),
+    };
+
+    let progress = match solver.progress {
+        CalcuProgress::Quiet => ProgressOutput::None,
+        CalcuProgress::Text => ProgressOutput::Text,
+        CalcuProgress::Json => ProgressOutput::Json,
+    };
+    let solution = Solver::new(items)
+        .goal(solver.goal)
+        .rank_size(solver.rank_size)
+        .constraints(constraints)
+        .control(SearchControl::new().progress_output(progress))
+        .solve();
+    match solution {
+        Ok(solution) => {
+            let ranked = CalcuResults {
+                results: solution.results,
+                fields,
+                num_items: solver.items.len(),
+            };
+            unsafe { *results = Box::into_raw(Box::new(ranked)) };
+            CalcuStatus::Ok
+        }
+        Err(err) => fail_with(err),
+    }
+}
+
+/// # Safety
+///
+/// `results` must come from `calcu_solver_run` and not be used afterwards,
+/// or be NULL.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_results_free(results: *mut CalcuResults) {
+    if !results.is_null() {
+        drop(unsafe { Box::from_raw(results) });
+    }
+}
+
+/// Number of formulas, best first.
+///
+/// # Safety
+///
+/// `results` must be live results or NULL.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_results_len(results: *const CalcuResults) -> usize {
+    unsafe { results.as_ref() }.map_or(0, |results| results.results.data.len())
+}
+
+/// Reads formula `rank` (0-based) into `*result`.
+///
+/// # Safety
+///
+/// `results` must be live results and `result` point to writable memory.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_results_get(
+    results: *const CalcuResults,
+    rank: usize,
+    result: *mut CalcuResult,
+) -> CalcuStatus {
+    let (Some(results), Some(result)) = (unsafe { results.as_ref() }, unsafe { result.as_mut() })
+    else {
+        return invalid_argument("results or result is NULL");
+    };
+    let Some(found) = results.results.data.get(rank) else {
+        return invalid_argument("rank out of range");
+    };
+    *result = CalcuResult {
+        diff: found.diff,
+        error: found.get_error(),
+        num_selected: found.permutation_select.count_ones() as usize,
+    };
+    CalcuStatus::Ok
+}
+
+/// Writes the sign of every solver item in formula `rank`: `1` added, `-1`
+/// subtracted, `0` not used. `len` must be at least the number of items.
+///
+/// # Safety
+///
+/// `results` must be live results and `signs` point to `len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_results_signs(
+    results: *const CalcuResults,
+    rank: usize,
+    signs: *mut i8,
+    len: usize,
+) -> CalcuStatus {
+    let Some(results) = (unsafe { results.as_ref() }) else {
+        return invalid_argument("results is NULL");
+    };
+    if signs.is_null() || len < results.num_items {
+        return invalid_argument("signs is NULL or shorter than the items");
+    }
+    let Some(found) = results.results.data.get(rank) else {
+        return invalid_argument("rank out of range");
+    };
+    let signs = unsafe { std::slice::from_raw_parts_mut(signs, len) };
+    signs.fill(0);
+    let searched = found.get_signs();
+    for (&item, sign) in results.fields.iter().zip(searched) {
+        signs[item] = sign;
+    }
+    CalcuStatus::Ok
+}
+
+/// Writes the indices of the solver items used by formula `rank`, in item
+/// order, up to `len` of them. Returns how many the formula uses, which may
+/// be more than `len`.
+///
+/// # Safety
+///
+/// `results` must be live results and `indices` point to `len` writable
+/// values.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn calcu_results_fields(
+    results: *const CalcuResults,
+    rank: usize,
+    indices: *mut usize,
+    len: usize,
+) -> usize {
+    let Some(results) = (unsafe { results.as_ref() }) else {
+        return 0;
+    };
+    let Some(found) = results.results.data.get(rank) else {
+        return 0;
+    };
+    let used: Vec<usize> = results
+        .fields
+        .iter()
+        .enumerate()
+        .filter(|(n, _)| (found.permutation_select >> n) & 1 == 1)
+        .map(|(_, &item)| item)
+        .collect();
+    if !indices.is_null() {
+        let indices = unsafe { std::slice::from_raw_parts_mut(indices, len) };
+        for (slot, item) in indices.iter_mut().zip(&used) {
+            *slot = *item;
+        }
+    }
+    used.len()
+}
@@ -0,0 +1,62 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::permutation::Permutation;
+
+/// The cells holding the values of a field in the original sheet, 1-based.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SheetRange {
+    pub first_row: u64,
+    pub first_column: usize,
+    pub last_row: u64,
+    pub last_column: usize,
+}
+
+impl Display for SheetRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}:{}{}",
+            column_name(self.first_column),
+            self.first_row,
+            column_name(self.last_column),
+            self.last_row
+        )
+    }
+}
+
+/// Spreadsheet name of a 1-based column: `A`, ..., `Z`, `AA`, ...
+pub fn column_name(mut column: usize) -> String {
+    let mut name = Vec::new();
+    while column > 0 {
+        column -= 1;
+        name.push(b'A' + (column % 26) as u8);
+        column /= 26;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+/// Builds the formula summing every period of the selected fields with
+/// their signs, e.g. `=SUM(B2:M2)-SUM(B5:M5)`, given the range of each
+/// field. `None` if a selected field has no known range.
+pub fn spreadsheet_formula<P: Permutation>(
+    result: &P,
+    ranges: &[Option<SheetRange>],
+) -> Option<String> {
+    let mut formula = String::from("=");
+    for (sign, range) in result.get_signs().into_iter().zip(ranges) {
+        if sign == 0 {
+            continue;
+        }
+        let range = range.as_ref()?;
+        if sign < 0 {
+            formula.push('-');
+        } else if formula.len() > 1 {
+            formula.push('+');
+        }
+        formula.push_str(format!("SUM({})", range).as_str());
+    }
+    Some(formula)
+}
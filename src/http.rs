@@ -0,0 +1,350 @@
+//! Local HTTP API: upload datasets, submit searches and poll them.
+//!
+//! | Request                   | Body                                | Answer                    |
+//! |---------------------------|-------------------------------------|---------------------------|
+//! | `POST /datasets/{name}`   | CSV, loader settings in the query   | the fields and periods    |
+//! | `GET /datasets`           |                                     | names of loaded datasets  |
+//! | `POST /jobs`              | a `solve` request as in server mode | the job id                |
+//! | `GET /jobs/{id}`          |                                     | state and progress        |
+//! | `GET /jobs/{id}/results`  |                                     | the ranking once done     |
+//! | `DELETE /jobs/{id}`       |                                     | cancels, or forgets a job |
+//!
+//! Searches run on the rayon pool, at most `max_jobs` at a time, the others
+//! wait in line. Only the last `keep_jobs` finished jobs are kept, older ones
+//! are forgotten when a job is submitted. Errors are `{"kind", "message"}`
+//! objects, as in server mode.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{Value, json};
+use tiny_http::{Header, Response};
+
+use cal_cu_lator::dataset::Dataset;
+use cal_cu_lator::run::RunOptions;
+use cal_cu_lator::{Error, SearchControl, loader, output};
+
+use crate::jobfile::LoaderSettings;
+use crate::server::{self, ErrorBody, SearchJob, SolveRequest};
+
+/// A status code and the JSON sent with it.
+type Reply = (u16, Value);
+
+enum JobState {
+    Queued,
+    Running,
+    Done(Value),
+    Failed(ErrorBody),
+}
+
+struct Job {
+    control: SearchControl,
+    percent: Arc<AtomicU32>,
+    state: Mutex<JobState>,
+}
+
+struct Queue {
+    waiting: VecDeque<(Arc<Job>, SearchJob, RunOptions)>,
+    running: usize,
+    max_jobs: usize,
+}
+
+pub struct Api {
+    datasets: Mutex<HashMap<String, Dataset>>,
+    jobs: Mutex<HashMap<u64, Arc<Job>>>,
+    next_id: AtomicU64,
+    queue: Mutex<Queue>,
+    keep_jobs: usize,
+}
+
+/// Answers requests on `127.0.0.1:port` until the process is stopped.
+pub fn serve(port: u16, max_jobs: usize, keep_jobs: usize) -> Result<(), Error> {
+    let api = Api::new(max_jobs, keep_jobs)?;
+    let address = format!("127.0.0.1:{}", port);
+    let server = tiny_http::Server::http(&address)
+        .map_err(|e| Error::io(&address, std::io::Error::other(e.to_string())))?;
+    eprintln!("Listening on http://{}", address);
+
+    let json = Header::from_bytes("Content-Type", "application/json").unwrap();
+    for mut request in server.incoming_requests() {
+        let mut body = String::new();
+        let (status, value) = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => api.route(request.method().as_str(), request.url(), &body),
+            Err(e) => error_reply(&Error::io("request", e)),
+        };
+        let response = Response::from_string(value.to_string())
+            .with_status_code(status)
+            .with_header(json.clone());
+        if let Err(e) = request.respond(response) {
+            eprintln!("{}", Error::io("response", e));
+        }
+    }
+    Ok(())
+}
+
+impl Api {
+    pub fn new(max_jobs: usize, keep_jobs: usize) -> Result<Arc<Self>, Error> {
+        if max_jobs == 0 {
+            return Err(Error::InvalidOption(
+                "at least one job must be able to run".to_string(),
+            ));
+        }
+        Ok(Arc::new(Api {
+            datasets: Mutex::new(HashMap::new()),
+            jobs: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            queue: Mutex::new(Queue {
+                waiting: VecDeque::new(),
+                running: 0,
+                max_jobs,
+            }),
+            keep_jobs,
+        }))
+    }
+
+    pub fn route(self: &Arc<Self>, method: &str, url: &str, body: &str) -> Reply {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let segments: Vec<String> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(percent_decode)
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        let reply = match (method, segments.as_slice()) {
+            ("GET", ["datasets"]) => {
+                let datasets = self.datasets.lock().unwrap();
+                let mut names: Vec<&String> = datasets.keys().collect();
+                names.sort();
+                Ok((200, json!(names)))
+            }
+            ("POST", ["datasets", name]) => self.upload(name, query, body),
+            ("POST", ["jobs"]) => self.submit(body),
+            (_, ["jobs", id, ..]) => {
+                let job = id.parse().ok().and_then(|id| {
+                    let job = self.jobs.lock().unwrap().get(&id).cloned()?;
+                    Some((id, job))
+                });
+                let Some((id, job)) = job else {
+                    return not_found(&format!("job {}", id));
+                };
+                match (method, &segments[2..]) {
+                    ("GET", []) => Ok((200, job.status(id))),
+                    ("GET", ["results"]) => Ok(job.results()),
+                    ("DELETE", []) => Ok(self.cancel(id, &job)),
+                    _ => return not_found(path),
+                }
+            }
+            _ => return not_found(path),
+        };
+        reply.unwrap_or_else(|err| error_reply(&err))
+    }
+
+    fn upload(&self, name: &str, query: &str, body: &str) -> Result<Reply, Error> {
+        let settings: serde_json::Map<String, Value> = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(key), Value::String(percent_decode(value)))
+            })
+            .collect();
+        let settings: LoaderSettings = serde_json::from_value(Value::Object(settings))
+            .map_err(|e| Error::InvalidOption(e.to_string()))?;
+        let dataset = loader::read_items(body.as_bytes(), name, &settings.options()?)?;
+        let summary = server::dataset_summary(name, &dataset);
+        self.datasets
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), dataset);
+        Ok((201, summary))
+    }
+
+    fn submit(self: &Arc<Self>, body: &str) -> Result<Reply, Error> {
+        let request: SolveRequest =
+            serde_json::from_str(body).map_err(|e| Error::from_json("request", &e))?;
+        request.solver.check_remote()?;
+        let mut options = request
+            .solver
+            .run_options(Default::default(), request.constraints)?;
+        let search = SearchJob::new(&self.datasets.lock().unwrap(), request.search, &options)?;
+
+        let percent = Arc::new(AtomicU32::new(0));
+        let reported = Arc::clone(&percent);
+        options.control = options
+            .control
+            .on_progress(move |p| reported.store(p, Ordering::Relaxed));
+        let job = Arc::new(Job {
+            control: options.control.clone(),
+            percent,
+            state: Mutex::new(JobState::Queued),
+        });
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            forget_finished(&mut jobs, self.keep_jobs);
+            jobs.insert(id, Arc::clone(&job));
+        }
+
+        let mut queue = self.queue.lock().unwrap();
+        if queue.running < queue.max_jobs {
+            queue.running += 1;
+            drop(queue);
+            self.start(job, search, options);
+        } else {
+            queue.waiting.push_back((job, search, options));
+        }
+        Ok((202, json!({ "job": id })))
+    }
+
+    /// Runs `search` on the rayon pool, then whatever waits in line.
+    fn start(self: &Arc<Self>, job: Arc<Job>, search: SearchJob, options: RunOptions) {
+        let api = Arc::clone(self);
+        rayon::spawn(move || {
+            job.run(search, &options);
+            let next = {
+                let mut queue = api.queue.lock().unwrap();
+                let next = queue.waiting.pop_front();
+                if next.is_none() {
+                    queue.running -= 1;
+                }
+                next
+            };
+            if let Some((job, search, options)) = next {
+                api.start(job, search, options);
+            }
+        });
+    }
+
+    fn cancel(&self, id: u64, job: &Job) -> Reply {
+        if job.is_finished() {
+            self.jobs.lock().unwrap().remove(&id);
+        } else {
+            job.control.cancel();
+        }
+        (200, job.status(id))
+    }
+}
+
+/// Forgets the finished jobs but the `keep` submitted last, ids grow with
+/// time.
+fn forget_finished(jobs: &mut HashMap<u64, Arc<Job>>, keep: usize) {
+    let mut finished: Vec<u64> = jobs
+        .iter()
+        .filter(|(_, job)| job.is_finished())
+        .map(|(&id, _)| id)
+        .collect();
+    finished.sort_unstable();
+    let forgotten = finished.len().saturating_sub(keep);
+    for id in &finished[..forgotten] {
+        jobs.remove(id);
+    }
+}
+
+impl Job {
+    fn is_finished(&self) -> bool {
+        matches!(
+            *self.state.lock().unwrap(),
+            JobState::Done(_) | JobState::Failed(_)
+        )
+    }
+
+    fn run(&self, search: SearchJob, options: &RunOptions) {
+        // cancelled while waiting in line
+        let result = if self.control.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            *self.state.lock().unwrap() = JobState::Running;
+            search.run(options)
+        };
+        *self.state.lock().unwrap() = match result {
+            Ok(ranking) => JobState::Done(json!(output::ranking_record(&ranking))),
+            Err(err) => JobState::Failed(ErrorBody::from(&err)),
+        };
+    }
+
+    fn status(&self, id: u64) -> Value {
+        let state = self.state.lock().unwrap();
+        let name = match &*state {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Done(_) => "done",
+            JobState::Failed(err) if err.kind == "cancelled" => "cancelled",
+            JobState::Failed(_) => "failed",
+        };
+        let mut status = json!({
+            "job": id,
+            "state": name,
+            "percent": self.percent.load(Ordering::Relaxed),
+        });
+        if let JobState::Failed(err) = &*state {
+            status["error"] = json!(err);
+        }
+        status
+    }
+
+    fn results(&self) -> Reply {
+        match &*self.state.lock().unwrap() {
+            JobState::Done(ranking) => (200, ranking.clone()),
+            JobState::Failed(err) => (409, json!(err)),
+            JobState::Queued | JobState::Running => (
+                409,
+                json!(ErrorBody {
+                    kind: "not_done",
+                    message: "the job is still running".to_string(),
+                }),
+            ),
+        }
+    }
+}
+
+fn error_reply(err: &Error) -> Reply {
+    let status = match err {
+        Error::Io { .. } => 500,
+        Error::Cancelled => 409,
+        _ => 400,
+    };
+    (status, json!(ErrorBody::from(err)))
+}
+
+fn not_found(what: &str) -> Reply {
+    (
+        404,
+        json!(ErrorBody {
+            kind: "not_found",
+            message: format!("{} not found", what),
+        }),
+    )
+}
+
+/// Decodes `%XX` escapes and `+` for spaces, as in query strings.
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let decoded = match b {
+            b'+' => Some(b' '),
+            b'%' => tail
+                .get(..2)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match (b, decoded) {
+            (b'%', Some(d)) => {
+                bytes.push(d);
+                rest = &tail[2..];
+            }
+            (_, Some(d)) => {
+                bytes.push(d);
+                rest = tail;
+            }
+            (_, None) => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,40 @@
+//! Ctrl-C stops the running search, which then keeps the best formulas found
+//! so far. A second Ctrl-C, or one with no search running, quits.
+
+use std::process;
+use std::sync::Mutex;
+
+use cal_cu_lator::SearchControl;
+
+static WATCHED: Mutex<Option<SearchControl>> = Mutex::new(None);
+
+/// Installs the handler, once per process.
+pub fn install() {
+    let installed = ctrlc::set_handler(|| {
+        let watched = WATCHED.lock().unwrap_or_else(|e| e.into_inner());
+        match watched.as_ref() {
+            Some(control) if !control.is_stopped() => {
+                eprintln!("Stopping, press Ctrl-C again to quit");
+                control.stop();
+            }
+            _ => process::exit(130),
+        }
+    });
+    if let Err(err) = installed {
+        eprintln!("Ctrl-C will quit instead of stopping searches: {}", err);
+    }
+}
+
+/// Makes Ctrl-C stop the searches of `control` until the guard is dropped.
+pub fn watch(control: &SearchControl) -> Watch {
+    *WATCHED.lock().unwrap_or_else(|e| e.into_inner()) = Some(control.clone());
+    Watch
+}
+
+pub struct Watch;
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        *WATCHED.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
@@ -1,5 +1,20 @@
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize)]
 pub struct Item {
     pub name: String,
     pub values: Vec<f64>,
 }
+
+impl Item {
+    pub fn total(&self) -> f64 {
+        self.values.iter().sum()
+    }
+
+    /// Returns a copy of this item holding only the values of the given
+    /// periods (0-based), in the order they are listed.
+    pub fn select_periods(&self, periods: &[usize]) -> Item {
+        Item {
+            name: self.name.clone(),
+            values: periods.iter().map(|&p| self.values[p]).collect(),
+        }
+    }
+}
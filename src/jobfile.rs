@@ -0,0 +1,239 @@
+use std::fs;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use cal_cu_lator::constraints::Constraints;
+use cal_cu_lator::loader::{self, Layout, LoaderOptions, MissingValues};
+use cal_cu_lator::numberformat::NumberFormat;
+use cal_cu_lator::output::OutputFormat;
+use cal_cu_lator::run::{Job, RunOptions};
+use cal_cu_lator::solver::DEFAULT_RANK_SIZE;
+use cal_cu_lator::{Error, Goal, ProgressOutput, SearchControl};
+
+const DEFAULT_CHECKPOINT_INTERVAL: f64 = 60.0;
+
+/// A run described in a TOML file, or a JSON one when the name ends with
+/// `.json`, instead of on the command line.
+///
+/// ```toml
+/// rank_size = 10
+/// format = "json"
+///
+/// [loader]
+/// locale = "it"
+///
+/// [constraints]
+/// pinned = ["BBBBB"]
+///
+/// [[files]]
+/// path = "test_data.csv"
+/// goals = [58200.23, 58000]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobFile {
+    /// Results kept for files that don't set their own.
+    #[serde(default = "default_rank_size")]
+    pub rank_size: usize,
+    #[serde(default)]
+    pub format: OutputFormat,
+    /// Where results are written, stdout when not set.
+    pub output: Option<String>,
+    #[serde(default)]
+    pub loader: LoaderSettings,
+    #[serde(default)]
+    pub solver: SolverSettings,
+    #[serde(default)]
+    pub constraints: Constraints,
+    #[serde(default)]
+    pub combine: CombineSettings,
+    pub files: Vec<FileJob>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoaderSettings {
+    pub locale: Option<String>,
+    pub delimiter: Option<String>,
+    pub layout: Layout,
+    pub missing: MissingValues,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SolverSettings {
+    pub holdout: Option<Vec<String>>,
+    pub equivalence_tolerance: Option<f64>,
+    pub keep_zero_rows: bool,
+    pub prune_redundant: bool,
+    /// Seconds after which each search stops with what it found so far.
+    pub time_limit: Option<f64>,
+    pub max_evaluations: Option<u64>,
+    /// Directory where searches save their progress and resume from.
+    pub checkpoint_dir: Option<String>,
+    /// Seconds between two checkpoints.
+    pub checkpoint_interval: Option<f64>,
+    /// How progress and notices are printed on stderr, as text unless set.
+    pub progress: Option<ProgressOutput>,
+}
+
+impl LoaderSettings {
+    pub fn options(&self) -> Result<LoaderOptions, Error> {
+        let number_format = match &self.locale {
+            Some(locale) => Some(
+                NumberFormat::from_locale(locale)
+                    .ok_or_else(|| Error::InvalidOption(format!("unknown locale {}", locale)))?,
+            ),
+            None => None,
+        };
+        let delimiter = match &self.delimiter {
+            Some(delimiter) => Some(loader::parse_delimiter(delimiter).ok_or_else(|| {
+                Error::InvalidOption(format!("invalid delimiter {:?}", delimiter))
+            })?),
+            None => None,
+        };
+        Ok(LoaderOptions {
+            delimiter,
+            number_format,
+            layout: self.layout,
+            missing_values: self.missing,
+        })
+    }
+}
+
+impl SolverSettings {
+    /// Fails on the settings that write files, which clients of the HTTP API
+    /// must not choose the place of.
+    pub fn check_remote(&self) -> Result<(), Error> {
+        if self.checkpoint_dir.is_some() {
+            return Err(Error::InvalidOption(
+                "checkpoint_dir can't be set in a request".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn run_options(
+        &self,
+        loader: LoaderOptions,
+        constraints: Constraints,
+    ) -> Result<RunOptions, Error> {
+        let mut control =
+            SearchControl::new().progress_output(self.progress.unwrap_or(ProgressOutput::Text));
+        if let Some(seconds) = self.time_limit {
+            let limit = Duration::try_from_secs_f64(seconds)
+                .map_err(|_| Error::InvalidOption(format!("invalid time limit {}", seconds)))?;
+            control = control.time_limit(limit);
+        }
+        if let Some(limit) = self.max_evaluations {
+            control = control.evaluation_limit(limit);
+        }
+        if let Some(dir) = &self.checkpoint_dir {
+            let seconds = self
+                .checkpoint_interval
+                .unwrap_or(DEFAULT_CHECKPOINT_INTERVAL);
+            let interval = Duration::try_from_secs_f64(seconds).map_err(|_| {
+                Error::InvalidOption(format!("invalid checkpoint interval {}", seconds))
+            })?;
+            control = control.checkpoint(dir, interval);
+        }
+        Ok(RunOptions {
+            loader,
+            holdout: self.holdout.clone(),
+            equivalence_tolerance: self.equivalence_tolerance,
+            keep_zero_rows: self.keep_zero_rows,
+            prune_redundant: self.prune_redundant,
+            constraints,
+            control,
+        })
+    }
+}
+
+/// How the rankings of several searches are merged.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CombineSettings {
+    pub enabled: bool,
+    pub rank_size: usize,
+}
+
+impl Default for CombineSettings {
+    fn default() -> Self {
+        CombineSettings {
+            enabled: true,
+            rank_size: DEFAULT_RANK_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileJob {
+    pub path: String,
+    /// One search is run for each goal.
+    pub goals: Vec<Goal>,
+    pub rank_size: Option<usize>,
+}
+
+/// The goals of a search, per period when cross-validating.
+pub fn goals_for(goal: &Goal, holdout: bool) -> Result<Vec<f64>, Error> {
+    match (goal, holdout) {
+        (Goal::Total(total), false) => Ok(vec![*total]),
+        (Goal::PerPeriod(goals), true) => Ok(goals.clone()),
+        (Goal::Total(_), true) => Err(Error::InvalidGoal(
+            "holdout needs per-period goals".to_string(),
+        )),
+        (Goal::PerPeriod(_), false) => Err(Error::InvalidGoal(
+            "per-period goals need a holdout".to_string(),
+        )),
+    }
+}
+
+fn default_rank_size() -> usize {
+    DEFAULT_RANK_SIZE
+}
+
+pub fn load(path: &str) -> Result<JobFile, Error> {
+    let content = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    if path.ends_with(".json") {
+        serde_json::from_str(&content).map_err(|e| Error::from_json(path, &e))
+    } else {
+        toml::from_str(&content).map_err(|e| {
+            let (line, column) = match e.span() {
+                Some(span) => {
+                    let before = &content[..span.start];
+                    let line = before.matches('\n').count() + 1;
+                    let column = before.len() - before.rfind('\n').map_or(0, |n| n + 1) + 1;
+                    (Some(line as u64), Some(column))
+                }
+                None => (None, None),
+            };
+            Error::parse(path, line, column, e.message())
+        })
+    }
+}
+
+impl JobFile {
+    /// Splits the file into one job per goal and the options they share.
+    pub fn jobs(&self) -> Result<(Vec<Job>, RunOptions), Error> {
+        let options = self
+            .solver
+            .run_options(self.loader.options()?, self.constraints.clone())?;
+
+        let mut jobs = Vec::new();
+        for file in &self.files {
+            for goal in &file.goals {
+                let goals = goals_for(goal, options.holdout.is_some())
+                    .map_err(|e| e.in_file(&file.path))?;
+                jobs.push(Job {
+                    file: file.path.clone(),
+                    goals,
+                    rank_size: file.rank_size.unwrap_or(self.rank_size),
+                });
+            }
+        }
+
+        Ok((jobs, options))
+    }
+}
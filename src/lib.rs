@@ -0,0 +1,1088 @@
+//! Finds the signed sums of payslip fields closest to a goal.
+
+mod checkpoint;
+pub mod combinedresult;
+pub mod constraints;
+pub mod control;
+pub mod crossvalidation;
+pub mod dataset;
+pub mod equivalence;
+pub mod error;
+pub mod ffi;
+pub mod formula;
+pub mod item;
+pub mod loader;
+mod masked_permutation;
+pub mod numberformat;
+pub mod output;
+pub mod permutation;
+mod progress;
+pub mod pruning;
+pub mod ranking;
+pub mod run;
+pub mod shard;
+pub mod singleresult;
+pub mod solver;
+pub mod sorted_vec;
+mod utils;
+pub mod verify;
+
+pub use combinedresult::CombinedResult;
+pub use constraints::Constraints;
+pub use control::{ProgressOutput, ProgressReport, SearchControl, SearchEvent};
+pub use error::Error;
+pub use item::Item;
+pub use permutation::{Permutation, PermutationKey};
+pub use ranking::Ranking;
+pub use singleresult::SingleResult;
+pub use solver::{Goal, Metric, Solution, Solver};
+pub use sorted_vec::SortedVec;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::equivalence::Equivalence;
+    use crate::loader::{Layout, LoaderOptions, MissingValues};
+    use crate::numberformat::NumberFormat;
+    use crate::run::{RunOptions, load_dataset, run_cu_solver};
+    use crate::solver::find_permutation;
+    use std::time::Duration;
+
+    /// Solves eight fields over two periods for a goal of 20, enough
+    /// formulas to stop, resume or split a search halfway.
+    fn solve_eight_fields(rank_size: usize, control: SearchControl) -> Solution {
+        let items: Vec<Item> = (0..8)
+            .map(|i| Item {
+                name: format!("F{}", i),
+                values: vec![f64::from(i) * 1.5 + 0.25, f64::from(i % 3)],
+            })
+            .collect();
+        Solver::new(items)
+            .goal(20.0)
+            .rank_size(rank_size)
+            .control(control)
+            .solve()
+            .unwrap()
+    }
+
+    /// The formulas of a ranking and their diffs, to compare rankings.
+    fn keys(results: &[SingleResult]) -> Vec<(PermutationKey, f64)> {
+        results.iter().map(|r| (r.get_key(), r.diff)).collect()
+    }
+
+    #[test]
+    fn test_find_permutation_empty_input() {
+        let empty_vec: Vec<Item> = Vec::new();
+        let result = find_permutation(&empty_vec, 1000.0, 5, 0);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().data.len(), 0);
+    }
+
+    #[test]
+    fn test_read_items_with_header_quotes_and_comments() {
+        let input = "\
+# exported from the payroll software
+name,jan,feb,mar
+
+\"Overtime, holidays\",1.50,2.00,3.00
+BBBBB,4,5,6,,
+";
+        let items = loader::read_items(input.as_bytes(), "input.csv", &LoaderOptions::default())
+            .unwrap()
+            .items;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].name, "Overtime, holidays");
+        assert_eq!(items[0].values, [1.5, 2.0, 3.0]);
+        assert_eq!(items[1].values, [4.0, 5.0, 6.0]);
+
+        let err = loader::read_items(
+            "AAAAA,1,2\nBBBBB,3,x4\n".as_bytes(),
+            "input.csv",
+            &LoaderOptions::default(),
+        );
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "input.csv:2:3: invalid number \"x4\""
+        );
+
+        // not a number is not an unknown value
+        for (input, cell) in [
+            ("AAAAA,1,2\nBBBBB,nan,2\n", "nan"),
+            ("AAAAA,1,2\nBBBBB,2,inf\n", "inf"),
+        ] {
+            let err = loader::read_items(input.as_bytes(), "input.csv", &LoaderOptions::default());
+            assert!(
+                err.unwrap_err()
+                    .to_string()
+                    .ends_with(&format!("invalid number \"{}\"", cell))
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_items_italian_export() {
+        let input = "\
+Voce;Gen;Feb;Mar
+AAAAA;1.694,46;€ 2.694,46;0,00
+\"BBBBB; extra\";(12,50);-1,5;1.000
+";
+        let options = LoaderOptions {
+            number_format: NumberFormat::from_locale("it"),
+            ..Default::default()
+        };
+        let items = loader::read_items(input.as_bytes(), "input.csv", &options)
+            .unwrap()
+            .items;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].values, [1694.46, 2694.46, 0.0]);
+        assert_eq!(items[1].name, "BBBBB; extra");
+        assert_eq!(items[1].values, [-12.5, -1.5, 1000.0]);
+
+        // `;` exports are read with a decimal comma unless told otherwise
+        let guessed = loader::read_items(input.as_bytes(), "input.csv", &LoaderOptions::default())
+            .unwrap()
+            .items;
+        assert_eq!(guessed[0].values, items[0].values);
+
+        // digits must be grouped by three around thousands separators
+        let en = NumberFormat::default();
+        assert_eq!(en.parse("1,694.46"), Some(1694.46));
+        assert_eq!(en.parse("12,345,678"), Some(12345678.0));
+        for misread in ["1,5", "2,00", "1.694,46", "1234,567", ",123", "1,2345"] {
+            assert_eq!(en.parse(misread), None, "{}", misread);
+        }
+        let options = LoaderOptions {
+            delimiter: Some(b';'),
+            number_format: Some(en),
+            ..Default::default()
+        };
+        let err = loader::read_items(input.as_bytes(), "input.csv", &options);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "input.csv:2:2: invalid number \"1.694,46\""
+        );
+    }
+
+    #[test]
+    fn test_read_items_transposed_layout() {
+        let input = "\
+Month,AAAAA,BBBBB
+Jan,1,10
+Feb,2,20
+Mar,3,30
+";
+        let items = loader::read_items(input.as_bytes(), "input.csv", &LoaderOptions::default())
+            .unwrap()
+            .items;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].name, "AAAAA");
+        assert_eq!(items[0].values, [1.0, 2.0, 3.0]);
+        assert_eq!(items[1].values, [10.0, 20.0, 30.0]);
+
+        let forced = LoaderOptions {
+            layout: Layout::Transposed,
+            ..Default::default()
+        };
+        let input = "Period,AAAAA,BBBBB\n1,1,10\n2,2\n";
+        let err = loader::read_items(input.as_bytes(), "input.csv", &forced);
+        assert_eq!(err.unwrap_err().to_string(), "input.csv:3:3: empty value");
+    }
+
+    #[test]
+    fn test_read_items_header_periods() {
+        let input = "\
+name,Jan,Feb,Mar,13th
+AAAAA,1,2,3,4
+BBBBB,5,6,7,8,,
+";
+        let dataset =
+            loader::read_items(input.as_bytes(), "input.csv", &LoaderOptions::default()).unwrap();
+        assert_eq!(dataset.periods, ["Jan", "Feb", "Mar", "13th"]);
+        assert_eq!(dataset.period_index("13TH"), Some(3));
+        assert_eq!(dataset.period_index("2"), Some(1));
+        assert_eq!(dataset.period_index("5"), None);
+
+        let dataset = load_dataset("test_data.csv", &LoaderOptions::default()).unwrap();
+        assert_eq!(dataset.periods.len(), 12);
+        assert_eq!(dataset.periods[11], "12");
+
+        // a typo in the first row is no header
+        let input = "AAAAA,1,2O,3\nBBBBB,4,5,6\n";
+        let err = loader::read_items(input.as_bytes(), "input.csv", &LoaderOptions::default());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "input.csv:1:3: invalid number \"2O\""
+        );
+
+        // numbered periods, the values of a field are hardly ever 1, 2, 3...
+        let input = "Voce,1,2,3\nAAAAA,1.5,2,3\n";
+        let dataset =
+            loader::read_items(input.as_bytes(), "input.csv", &LoaderOptions::default()).unwrap();
+        assert_eq!(dataset.periods, ["1", "2", "3"]);
+        assert_eq!(dataset.items.len(), 1);
+        assert_eq!(dataset.items[0].name, "AAAAA");
+
+        let input = "name,Jan,Feb,Mar\nAAAAA,1,2,3\nBBBBB,5,6\n";
+        let err = loader::read_items(input.as_bytes(), "input.csv", &LoaderOptions::default());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "input.csv:3: BBBBB has 2 values but there are 3 periods"
+        );
+    }
+
+    #[test]
+    fn test_read_items_blank_cells() {
+        let input = "\
+name,Jan,Feb,Mar
+AAAAA,1,,3
+BBBBB,4,5,
+CCCCC,7,8,9
+";
+        let read = |missing_values| {
+            let options = LoaderOptions {
+                missing_values,
+                ..Default::default()
+            };
+            loader::read_items(input.as_bytes(), "input.csv", &options)
+        };
+
+        assert_eq!(
+            read(MissingValues::Error).unwrap_err().to_string(),
+            "input.csv:2:3: empty value"
+        );
+
+        let dataset = read(MissingValues::Zero).unwrap();
+        assert_eq!(dataset.items[0].values, [1.0, 0.0, 3.0]);
+        assert_eq!(dataset.items[1].values, [4.0, 5.0, 0.0]);
+        assert_eq!(dataset.missing.len(), 2);
+        assert_eq!(
+            dataset.missing[1].to_string(),
+            "BBBBB, Mar (line 3, column 4)"
+        );
+
+        let dataset = read(MissingValues::Unknown).unwrap();
+        assert!(dataset.items[0].values[1].is_nan());
+        assert_eq!(dataset.unknown_periods(), [1, 2]);
+        let goals = [12.0, 0.0, 0.0];
+        let rank = crossvalidation::cross_validate(
+            &dataset.items,
+            &goals,
+            &[1],
+            1,
+            0,
+            &SearchControl::default(),
+        );
+        // Feb is unknown, nothing is left to validate on
+        assert!(rank.is_err());
+        // without held-out periods unknown values would have to be zeros
+        let options = RunOptions {
+            loader: LoaderOptions {
+                missing_values: MissingValues::Unknown,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let err = run::solve_dataset(dataset, "input.csv", &[12.0], 1, &options);
+        assert!(matches!(err, Err(Error::InvalidOption(_))));
+
+        let dataset = read(MissingValues::Reject).unwrap();
+        assert_eq!(dataset.rejected, ["AAAAA", "BBBBB"]);
+        assert_eq!(dataset.items.len(), 1);
+    }
+
+    #[test]
+    fn test_ranking_json_output() {
+        let ranking =
+            run_cu_solver("test_data.csv", &[58200.23], 2, &RunOptions::default()).unwrap();
+
+        let json = serde_json::to_value(output::ranking_record(&ranking)).unwrap();
+        assert_eq!(json["file"], "test_data.csv");
+        assert_eq!(json["goal"], 58200.23);
+        assert_eq!(json["field_names"].as_array().unwrap().len(), 7);
+        let best = &json["results"][0];
+        assert_eq!(best["rank"], 1);
+        assert_eq!(
+            best["selected"],
+            serde_json::json!([
+                {"name": "AAAAA", "sign": 1},
+                {"name": "BBBBB", "sign": 1},
+                {"name": "CCCCC", "sign": 1},
+                {"name": "FFFFF", "sign": -1},
+            ])
+        );
+        assert_eq!(best["error"], 23.399999999979627);
+        assert_eq!(best["total"], 58200.23 + 23.399999999979627);
+        assert!(best.get("validation_error").is_none());
+
+        let lines = output::ranking_lines(&ranking).unwrap();
+        assert_eq!(lines.len(), 2);
+        let second: serde_json::Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(second["type"], "result");
+        assert_eq!(second["file"], "test_data.csv");
+        assert_eq!(second["rank"], 2);
+    }
+
+    #[test]
+    fn test_ranking_csv_output() {
+        let options = RunOptions::default();
+        let rankings = [
+            run_cu_solver("test_data.csv", &[58200.23], 1, &options).unwrap(),
+            run_cu_solver("test_data_larger.csv", &[3110.76], 1, &options).unwrap(),
+        ];
+        let mut combined = SortedVec::new(10);
+        let best = &rankings[1].results.data[0];
+        combined.insert_ordered(CombinedResult::new(
+            best.field_names.clone(),
+            best.permutation_sign,
+            best.permutation_select,
+        ));
+
+        let mut written = Vec::new();
+        output::write_csv(&mut written, &rankings, &combined).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(
+            lines[0],
+            "source,rank,AAAAA,BBBBB,CCCCC,DDDDD,EEEEE,FFFFF,ADDED,GGGGG,HHHHH,IIIII,JJJJJ,total,goal,error,diff"
+        );
+        assert_eq!(
+            lines[1],
+            "test_data.csv,1,1,1,1,0,0,-1,0,0,0,0,0,58223.62999999998,58200.23,23.399999999979627,23.399999999979627"
+        );
+        assert!(lines[2].starts_with("test_data_larger.csv,1,0,1,1,-1,1,-1,0,0,1,0,-1,"));
+        assert_eq!(lines[3], "combined,1,0,1,1,-1,1,-1,0,0,1,0,-1,,,0,0");
+
+        // combined results alone still have their field columns
+        let mut written = Vec::new();
+        output::write_csv(&mut written, &[], &combined).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(
+            lines[0],
+            "source,rank,AAAAA,BBBBB,CCCCC,DDDDD,EEEEE,FFFFF,GGGGG,HHHHH,IIIII,JJJJJ,total,goal,error,diff"
+        );
+        assert_eq!(lines[1], "combined,1,0,1,1,-1,1,-1,0,1,0,-1,,,0,0");
+    }
+
+    #[test]
+    fn test_combine_by_field_names() {
+        // pruning ZZZZZ in a.csv makes BBBBB take its bit
+        let dir = std::env::temp_dir();
+        let files = [
+            ("cal_cu_lator_combine_a.csv", "AAAAA,1\nZZZZZ,0\nBBBBB,2\n"),
+            ("cal_cu_lator_combine_b.csv", "AAAAA,1\nZZZZZ,2\nBBBBB,7\n"),
+        ];
+        let rankings: Vec<Ranking> = files
+            .iter()
+            .map(|(name, content)| {
+                let path = dir.join(name);
+                std::fs::write(&path, content).unwrap();
+                let ranking =
+                    run_cu_solver(path.to_str().unwrap(), &[3.0], 30, &RunOptions::default());
+                std::fs::remove_file(path).unwrap();
+                ranking.unwrap()
+            })
+            .collect();
+        assert_eq!(rankings[0].field_names, ["AAAAA", "BBBBB"]);
+
+        let combined = combinedresult::combine(&rankings, 30);
+        let formula = |result: &CombinedResult| -> Vec<(String, i8)> {
+            let names = result.get_field_names().iter().cloned();
+            names
+                .zip(result.get_signs())
+                .filter(|(_, sign)| *sign != 0)
+                .collect()
+        };
+        let best = &combined.data[0];
+        assert_eq!(
+            formula(best),
+            [("AAAAA".to_string(), 1), ("ZZZZZ".to_string(), 1)]
+        );
+        assert_eq!(best.get_diff(), 0.0);
+        // 0 off in a.csv, 5 off in b.csv
+        let both = combined
+            .data
+            .iter()
+            .find(|r| formula(r) == [("AAAAA".to_string(), 1), ("BBBBB".to_string(), 1)])
+            .unwrap();
+        assert_eq!(both.get_diff(), 2.5);
+    }
+
+    #[test]
+    fn test_spreadsheet_formula() {
+        assert_eq!(formula::column_name(2), "B");
+        assert_eq!(formula::column_name(26), "Z");
+        assert_eq!(formula::column_name(28), "AB");
+
+        let ranking =
+            run_cu_solver("test_data.csv", &[58200.23], 1, &RunOptions::default()).unwrap();
+        let best = ranking.results.data.first().unwrap();
+        assert_eq!(
+            ranking.formula(best).unwrap(),
+            "=SUM(B1:M1)+SUM(B2:M2)+SUM(B3:M3)-SUM(B6:M6)"
+        );
+
+        let input = "# comment\nMonth,AAAAA,BBBBB\nJan,1,10\nFeb,2,20\n";
+        let dataset =
+            loader::read_items(input.as_bytes(), "input.csv", &LoaderOptions::default()).unwrap();
+        assert_eq!(dataset.range_of("BBBBB").unwrap().to_string(), "C3:C4");
+    }
+
+    #[test]
+    fn test_cross_validate_reports_held_out_error() {
+        let items = vec![
+            Item {
+                name: "AAAAA".to_string(),
+                values: vec![1.0, 1.0, 1.0, 1.0],
+            },
+            Item {
+                name: "BBBBB".to_string(),
+                values: vec![10.0, 10.0, 10.0, 50.0],
+            },
+        ];
+        let goals = [11.0, 11.0, 11.0, 11.0];
+
+        let rank =
+            crossvalidation::cross_validate(&items, &goals, &[3], 2, 0, &SearchControl::default())
+                .unwrap();
+        let best = rank.data.first().unwrap();
+        assert_eq!(best.permutation_select, 0b11);
+        assert_eq!(best.permutation_sign, 0b11);
+        assert_eq!(best.diff, 0.0);
+        assert_eq!(best.get_validation_error(), Some(40.0));
+
+        assert!(
+            crossvalidation::cross_validate(
+                &items,
+                &goals,
+                &[0, 1, 2, 3],
+                2,
+                0,
+                &SearchControl::default()
+            )
+            .is_err()
+        );
+        assert!(
+            crossvalidation::cross_validate(
+                &items,
+                &goals[..3],
+                &[2],
+                2,
+                0,
+                &SearchControl::default()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_collapse_equivalent_fields() {
+        let items = vec![
+            Item {
+                name: "AAAAA".to_string(),
+                values: vec![0.0, 295.94],
+            },
+            Item {
+                name: "BBBBB".to_string(),
+                values: vec![1.0, 2.0],
+            },
+            Item {
+                name: "CCCCC".to_string(),
+                values: vec![295.94, 0.0],
+            },
+        ];
+        let by_values = equivalence::collapse_equivalent(
+            items.iter().map(|i| i.select_periods(&[0, 1])).collect(),
+            0.0,
+            Equivalence::Values,
+        );
+        assert_eq!(by_values.len(), 3);
+
+        let by_total = equivalence::collapse_equivalent(items, 0.0, Equivalence::Total);
+        let names: Vec<&str> = by_total.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["AAAAA | CCCCC", "BBBBB"]);
+        assert_eq!(by_total[0].values, [0.0, 295.94]);
+    }
+
+    #[test]
+    fn test_prune_zero_and_redundant_fields() {
+        let items = vec![
+            Item {
+                name: "AAAAA".to_string(),
+                values: vec![1.5, 0.0, 2.25],
+            },
+            Item {
+                name: "ZZZZZ".to_string(),
+                values: vec![0.0, 0.0, 0.0],
+            },
+            Item {
+                name: "BBBBB".to_string(),
+                values: vec![0.1, 7.0, 0.0],
+            },
+            Item {
+                name: "CCCCC".to_string(),
+                values: vec![1.4, -7.0, 2.25],
+            },
+            Item {
+                name: "DDDDD".to_string(),
+                values: vec![3.0, 0.0, 4.5],
+            },
+        ];
+
+        let items_again = items.clone();
+        let (kept, pruned) = pruning::prune(items, true, true);
+        let names: Vec<&str> = kept.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["AAAAA", "BBBBB", "DDDDD"]);
+        assert_eq!(
+            pruned,
+            [
+                pruning::Pruned {
+                    name: "ZZZZZ".to_string(),
+                    reason: pruning::PruneReason::Zero,
+                },
+                pruning::Pruned {
+                    name: "CCCCC".to_string(),
+                    reason: pruning::PruneReason::Combination(" + AAAAA - BBBBB".to_string()),
+                },
+            ]
+        );
+
+        // kept zero fields are not an empty combination
+        let (kept, pruned) = pruning::prune(items_again, false, true);
+        let names: Vec<&str> = kept.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["AAAAA", "ZZZZZ", "BBBBB", "DDDDD"]);
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].name, "CCCCC");
+    }
+
+    #[test]
+    fn test_verify_formula() {
+        assert_eq!(
+            verify::parse_formula("+ AAAAA - BBBBB CC").unwrap(),
+            [(1, "AAAAA".to_string()), (-1, "BBBBB CC".to_string())]
+        );
+        assert_eq!(
+            verify::parse_formula("AAAAA -BBBBB").unwrap(),
+            [(1, "AAAAA".to_string()), (-1, "BBBBB".to_string())]
+        );
+        assert!(verify::parse_formula("+ - AAAAA").is_err());
+        assert!(verify::parse_formula("AAAAA +").is_err());
+
+        let items = vec![
+            Item {
+                name: "AAAAA".to_string(),
+                values: vec![1.5, 2.0],
+            },
+            Item {
+                name: "BBBBB".to_string(),
+                values: vec![0.25, 0.25],
+            },
+        ];
+        let terms = verify::parse_formula("- BBBBB + AAAAA").unwrap();
+        assert_eq!(verify::formula_total(&terms, &items).unwrap(), 3.0);
+        let terms = verify::parse_formula("+ CCCCC").unwrap();
+        assert!(verify::formula_total(&terms, &items).is_err());
+    }
+
+    #[test]
+    fn test_search_budget() {
+        let items: Vec<Item> = (0..8)
+            .map(|i| Item {
+                name: format!("F{}", i),
+                values: vec![f64::from(i + 1)],
+            })
+            .collect();
+
+        let solution = Solver::new(items.clone()).goal(36.0).solve().unwrap();
+        assert!(!solution.partial);
+        assert_eq!(solution.results.data[0].diff, 0.0);
+
+        // every single field selection fits, hardly anything else does
+        let solution = Solver::new(items.clone())
+            .goal(36.0)
+            .rank_size(100)
+            .control(SearchControl::new().evaluation_limit(20))
+            .solve()
+            .unwrap();
+        assert!(solution.partial);
+        assert!(!solution.results.data.is_empty());
+        assert!(solution.results.data.len() < 100);
+
+        let solution = Solver::new(items.clone())
+            .goal(36.0)
+            .control(SearchControl::new().time_limit(Duration::ZERO))
+            .solve()
+            .unwrap();
+        assert!(solution.partial);
+        assert!(solution.results.data.is_empty());
+
+        // a selection of many fields is stopped halfway through its signs
+        let many: Vec<Item> = (0..16)
+            .map(|i| Item {
+                name: format!("F{}", i),
+                values: vec![f64::from(i + 1)],
+            })
+            .collect();
+        let all = many.iter().map(|i| i.name.clone()).collect();
+        let solution = Solver::new(many)
+            .goal(36.0)
+            .constraints(Constraints {
+                pinned: all,
+                excluded: Vec::new(),
+            })
+            .control(SearchControl::new().evaluation_limit(5000))
+            .solve()
+            .unwrap();
+        assert!(solution.partial);
+        assert!(!solution.results.data.is_empty());
+
+        // stopped searches keep their results, cancelled ones fail
+        let control = SearchControl::new();
+        control.stop();
+        let solution = Solver::new(items.clone())
+            .goal(36.0)
+            .control(control.clone())
+            .solve()
+            .unwrap();
+        assert!(solution.partial);
+        let solution = Solver::new(items.clone())
+            .goal(36.0)
+            .control(control.renewed())
+            .solve()
+            .unwrap();
+        assert!(!solution.partial);
+        control.cancel();
+        assert!(matches!(
+            Solver::new(items).goal(36.0).control(control).solve(),
+            Err(Error::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let dir =
+            std::env::temp_dir().join(format!("cal_cu_lator_checkpoint_{}", std::process::id()));
+        let solve = |control| solve_eight_fields(20, control);
+        let full = solve(SearchControl::new());
+
+        let control = SearchControl::new()
+            .checkpoint(&dir, Duration::ZERO)
+            .checkpoint_chunk(100);
+        let stopped = solve(control.clone().evaluation_limit(500));
+        assert!(stopped.partial);
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let checkpoint: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap(),
+        )
+        .unwrap();
+        assert!(checkpoint["next_select"].as_u64().unwrap() > 1);
+
+        // the resumed search ends as if it never stopped, and cleans up
+        let resumed = solve(control);
+        assert!(!resumed.partial);
+        assert_eq!(keys(&resumed.results.data), keys(&full.results.data));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_shard_merge() {
+        let solve = |control| solve_eight_fields(20, control);
+        let full = solve(SearchControl::new());
+
+        let shards: Vec<shard::ShardFile> = (1..=3)
+            .map(|index| {
+                let shard = shard::Shard { index, count: 3 };
+                let solution = solve(SearchControl::new().shard(shard));
+                let ranking = Ranking {
+                    file: "a.csv".to_string(),
+                    field_names: solution.field_names,
+                    goal: solution.goal,
+                    results: solution.results,
+                    ranges: Vec::new(),
+                    partial: solution.partial,
+                };
+                let mut written = Vec::new();
+                shard::write(&mut written, shard, vec![ranking]).unwrap();
+                shard::read(written.as_slice(), "shard").unwrap()
+            })
+            .collect();
+        assert_eq!(shards[0].rankings[0].results.data[0].field_names.len(), 8);
+
+        assert_eq!(
+            shard::missing(&shards[1..]),
+            [shard::Shard { index: 1, count: 3 }]
+        );
+        let merged = shard::merge(shards).unwrap();
+        assert!(!merged[0].partial);
+        assert_eq!(keys(&merged[0].results.data), keys(&full.results.data));
+
+        assert!("0/3".parse::<shard::Shard>().is_err());
+        assert!("2".parse::<shard::Shard>().is_err());
+    }
+
+    #[test]
+    fn test_search_events() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let solution = solve_eight_fields(5, SearchControl::new().send_events(sender));
+
+        let events: Vec<SearchEvent> = receiver.iter().collect();
+        let mut reports = Vec::new();
+        let mut last_results = Vec::new();
+        let mut notices = Vec::new();
+        for event in events {
+            match event {
+                SearchEvent::Progress(report) => reports.push(report),
+                SearchEvent::Results(results) => {
+                    assert!(results.len() <= 5);
+                    assert!(results.windows(2).all(|w| w[0] <= w[1]));
+                    last_results = results;
+                }
+                SearchEvent::Notice(notice) => notices.push(notice),
+            }
+        }
+        assert_eq!(
+            notices,
+            ["Using 11111111 mask to compute permutations on 8 fields"]
+        );
+        // progress counts formulas, every signed sum of 8 fields but the empty one
+        // reports from different threads may arrive out of order
+        let done = reports.iter().find(|r| r.percent == 100).unwrap();
+        assert_eq!((done.evaluated, done.total), (6560, 6560));
+        // the last improvement is the final ranking
+        assert_eq!(keys(&last_results), keys(&solution.results.data));
+    }
+
+    #[test]
+    fn test_solver_builder() {
+        let items = || {
+            vec![
+                Item {
+                    name: "AAAAA".to_string(),
+                    values: vec![10.0, 0.0],
+                },
+                Item {
+                    name: "BBBBB".to_string(),
+                    values: vec![0.0, 10.0],
+                },
+                Item {
+                    name: "CCCCC".to_string(),
+                    values: vec![3.0, 3.0],
+                },
+            ]
+        };
+
+        // by total AAAAA and BBBBB can't be told apart
+        let solution = Solver::new(items())
+            .goal(vec![10.0, 0.0])
+            .metric(Metric::PerPeriod)
+            .rank_size(3)
+            .solve()
+            .unwrap();
+        assert_eq!(solution.goal, 10.0);
+        let best = &solution.results.data[0];
+        assert_eq!(best.get_signs(), [1, 0, 0]);
+        assert_eq!(best.diff, 0.0);
+        assert_eq!(solution.results.data[1].diff, 6.0);
+
+        let solution = Solver::new(items())
+            .goal(7.0)
+            .constraints(Constraints {
+                pinned: vec!["CCCCC".to_string()],
+                excluded: vec!["AAAAA".to_string()],
+            })
+            .solve()
+            .unwrap();
+        assert_eq!(solution.field_names, ["BBBBB", "CCCCC"]);
+        assert!(solution.results.data.iter().all(|r| r.get_signs()[1] != 0));
+
+        assert!(
+            Solver::new(items())
+                .goal(10.0)
+                .metric(Metric::PerPeriod)
+                .solve()
+                .is_err()
+        );
+        assert!(Solver::new(items()).goal(vec![1.0]).solve().is_err());
+        assert!(
+            Solver::new(items())
+                .goal(10.0)
+                .holdout(vec![1])
+                .solve()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_typed_errors() {
+        let err = loader::read_items(
+            "AAAAA,1,2\nBBBBB,3,x4\n".as_bytes(),
+            "input.csv",
+            &LoaderOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Parse {
+                line: Some(2),
+                column: Some(3),
+                ..
+            }
+        ));
+
+        let err = load_dataset("missing.csv", &LoaderOptions::default()).unwrap_err();
+        assert!(matches!(err, Error::Io { .. }));
+
+        let items: Vec<Item> = (0..32)
+            .map(|n| Item {
+                name: n.to_string(),
+                values: vec![1.0],
+            })
+            .collect();
+        let err = find_permutation(&items, 1.0, 1, 0).unwrap_err();
+        assert_eq!(err.to_string(), "too many fields: 32 (max 31 supported)");
+
+        let err = Solver::new(Vec::new()).goal(1.0).solve().unwrap_err();
+        assert!(matches!(err, Error::EmptyInput(_)));
+        let err = Solver::new(items).goal(f64::NAN).solve().unwrap_err();
+        assert!(matches!(err, Error::InvalidGoal(_)));
+
+        let options = RunOptions {
+            constraints: Constraints {
+                pinned: vec!["ZZZZZ".to_string()],
+                excluded: Vec::new(),
+            },
+            ..Default::default()
+        };
+        let err = run_cu_solver("test_data.csv", &[1000.0], 1, &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "test_data.csv: pinned field ZZZZZ not found or pruned"
+        );
+        assert!(matches!(err, Error::InvalidOption(_)));
+    }
+
+    #[test]
+    fn test_ffi_round_trip() {
+        use crate::ffi::*;
+        use std::ffi::CStr;
+
+        let csv = "AAAAA,10,0\nBBBBB,0,10\nCCCCC,3,3\n";
+        let values = [1.0, 2.0];
+        unsafe {
+            let solver = calcu_solver_new();
+            assert_eq!(
+                calcu_solver_load_csv(solver, csv.as_ptr(), csv.len()),
+                CalcuStatus::Ok
+            );
+            assert_eq!(
+                calcu_solver_add_item(solver, c"DDDDD".as_ptr(), values.as_ptr(), values.len()),
+                CalcuStatus::Ok
+            );
+            assert_eq!(calcu_solver_num_items(solver), 4);
+            calcu_solver_set_goal(solver, 16.0);
+            calcu_solver_set_rank_size(solver, 3);
+            assert_eq!(
+                calcu_solver_set_progress(solver, CalcuProgress::Quiet),
+                CalcuStatus::Ok
+            );
+            assert_eq!(calcu_solver_exclude(solver, 1), CalcuStatus::Ok);
+            assert_eq!(calcu_solver_pin(solver, 4), CalcuStatus::InvalidArgument);
+
+            let mut results = std::ptr::null_mut();
+            assert_eq!(calcu_solver_run(solver, &mut results), CalcuStatus::Ok);
+            assert_eq!(calcu_results_len(results), 3);
+            let mut best = CalcuResult::default();
+            assert_eq!(calcu_results_get(results, 0, &mut best), CalcuStatus::Ok);
+            assert_eq!(best.diff, 0.0);
+            assert_eq!(best.num_selected, 2);
+            let mut signs = [9_i8; 4];
+            calcu_results_signs(results, 0, signs.as_mut_ptr(), signs.len());
+            assert_eq!(signs, [1, 0, 1, 0]);
+            let mut fields = [0_usize; 4];
+            let used = calcu_results_fields(results, 0, fields.as_mut_ptr(), fields.len());
+            assert_eq!(&fields[..used], [0, 2]);
+            calcu_results_free(results);
+
+            let bad = "AAAAA,1,2\nBBBBB,3,x4\n";
+            assert_eq!(
+                calcu_solver_load_csv(solver, bad.as_ptr(), bad.len()),
+                CalcuStatus::Parse
+            );
+            let message = CStr::from_ptr(calcu_last_error()).to_str().unwrap();
+            assert_eq!(message, "<buffer>:2:3: invalid number \"x4\"");
+            calcu_solver_free(solver);
+        }
+
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/cal_cu_lator.h"));
+        let committed = include_str!("../include/cal_cu_lator.h");
+        assert!(
+            generated == committed,
+            "include/cal_cu_lator.h is out of date, run CALCU_UPDATE_HEADER=1 cargo build"
+        );
+    }
+
+    #[test]
+    fn test_find_permutation_from_input_file() {
+        let filename = "test_data.csv";
+        let goal = 58200.23;
+        let rank_size = 10;
+        let p_rank_result = run_cu_solver(filename, &[goal], rank_size, &RunOptions::default());
+        assert!(p_rank_result.is_ok());
+        let rank = p_rank_result.unwrap().results;
+
+        let descriptions = vec![
+            "AAAAA".to_string(),
+            "BBBBB".to_string(),
+            "CCCCC".to_string(),
+            "DDDDD".to_string(),
+            "EEEEE".to_string(),
+            "FFFFF".to_string(),
+            "ADDED".to_string(),
+        ];
+
+        let expected_rank = [
+            SingleResult::new(
+                descriptions.clone(),
+                0b111,
+                0b100111,
+                0b1111111,
+                23.399999999979627,
+                23.399999999979627,
+            ),
+            SingleResult::new(
+                descriptions.clone(),
+                0b1100111,
+                0b1111111,
+                0b1111111,
+                25.62999999998283,
+                -25.62999999998283,
+            ),
+            SingleResult::new(
+                descriptions.clone(),
+                0b10,
+                0b10110,
+                0b1111111,
+                29.949999999989814,
+                29.949999999989814,
+            ),
+            SingleResult::new(
+                descriptions.clone(),
+                0b1110110,
+                0b1111110,
+                0b1111111,
+                45.250000000007276,
+                45.250000000007276,
+            ),
+            SingleResult::new(
+                descriptions.clone(),
+                0b10,
+                0b111,
+                0b1111111,
+                82.5800000000163,
+                -82.5800000000163,
+            ),
+            SingleResult::new(
+                descriptions.clone(),
+                0b10110,
+                0b110110,
+                0b1111111,
+                89.13000000002648,
+                -89.13000000002648,
+            ),
+            SingleResult::new(
+                descriptions.clone(),
+                0b10010,
+                0b10111,
+                0b111111,
+                100.82999999997992,
+                100.82999999997992,
+            ),
+            SingleResult::new(
+                descriptions.clone(),
+                0b1100110,
+                0b1101110,
+                0b1111111,
+                138.15999999998894,
+                -138.15999999998894,
+            ),
+            SingleResult::new(
+                descriptions.clone(),
+                0b1100111,
+                0b1101111,
+                0b1111111,
+                157.7800000000134,
+                157.7800000000134,
+            ),
+            SingleResult::new(
+                descriptions.clone(),
+                0b111,
+                0b110111,
+                0b1111111,
+                160.0100000000166,
+                -160.0100000000166,
+            ),
+        ];
+
+        rank.data.iter().enumerate().for_each(|(i, res)| {
+            let b = expected_rank.get(i).unwrap();
+            assert_eq!(res, b);
+        });
+    }
+
+    #[test]
+    fn test_find_permutation_from_larger_input_file() {
+        let filename = "test_data_larger.csv";
+        let goal = 3110.76;
+        let rank_size = 3;
+        let p_rank_result = run_cu_solver(filename, &[goal], rank_size, &RunOptions::default());
+        assert!(p_rank_result.is_ok());
+        let rank = p_rank_result.unwrap().results;
+
+        let descriptions = vec![
+            "AAAAA".to_string(),
+            "BBBBB".to_string(),
+            "CCCCC".to_string(),
+            "DDDDD".to_string(),
+            "EEEEE".to_string(),
+            "FFFFF".to_string(),
+            "GGGGG".to_string(),
+            "HHHHH".to_string(),
+            "IIIII".to_string(),
+            "JJJJJ".to_string(),
+        ];
+
+        let expected_rank = [
+            SingleResult::new(
+                descriptions.clone(),
+                0b10010110,
+                0b1010111110,
+                0b1111111111,
+                0.0000000000004547473508864641,
+                -0.0000000000004547473508864641,
+            ),
+            SingleResult::new(
+                descriptions.clone(),
+                0b11000000,
+                0b111110001,
+                0b111111111,
+                0.15999999999939973,
+                0.15999999999939973,
+            ),
+            SingleResult::new(
+                descriptions.clone(),
+                0b1010000,
+                0b1101011100,
+                0b1111111111,
+                0.3400000000001455,
+                0.3400000000001455,
+            ),
+        ];
+
+        rank.data.iter().enumerate().for_each(|(i, res)| {
+            let b = expected_rank.get(i).unwrap();
+            assert_eq!(res, b);
+        });
+    }
+}
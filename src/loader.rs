@@ -0,0 +1,474 @@
+use std::io::Read;
+
+use csv::{ReaderBuilder, Trim};
+
+use crate::dataset::{Dataset, MissingValue};
+use crate::error::Error;
+use crate::formula::SheetRange;
+use crate::item::Item;
+use crate::numberformat::NumberFormat;
+
+const COMMENT: u8 = b'#';
+const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+// lines looked at when guessing the delimiter
+const SNIFF_LINES: usize = 10;
+
+/// Whether fields are written one per row or one per column.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// Transposed when the header names fields and the first column names
+    /// periods, fields in rows otherwise.
+    #[default]
+    Auto,
+    /// One field per row, one period per column.
+    Rows,
+    /// One period per row, one field per column, names in the header.
+    Transposed,
+}
+
+/// What to do with blank cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum MissingValues {
+    /// Fail loading, pointing at the blank cell.
+    #[default]
+    Error,
+    /// Read blank cells as zero.
+    Zero,
+    /// Keep blank cells as unknown (`NaN`) values, their periods are left
+    /// out of per-period matching. Searches without held-out periods reject
+    /// them.
+    Unknown,
+    /// Leave out the fields with blank cells.
+    Reject,
+}
+
+/// How an export is laid out.
+#[derive(Debug, Clone, Default)]
+pub struct LoaderOptions {
+    /// Field delimiter, guessed from the input when not set.
+    pub delimiter: Option<u8>,
+    /// Number format, guessed from the delimiter when not set.
+    pub number_format: Option<NumberFormat>,
+    pub layout: Layout,
+    pub missing_values: MissingValues,
+}
+
+/// A field as read, before blank cells are dealt with.
+struct RawItem {
+    name: String,
+    /// `None` for blank cells.
+    values: Vec<Option<f64>>,
+    /// Line and column of each value.
+    locations: Vec<(u64, usize)>,
+}
+
+/// A record with the line it starts on.
+struct Row {
+    line: u64,
+    cells: Vec<String>,
+}
+
+impl Row {
+    /// Number of cells without the empty ones spreadsheets pad rows with,
+    /// keeping at least `width` cells when the row has them.
+    fn width(&self, width: usize) -> usize {
+        let used = self
+            .cells
+            .iter()
+            .rposition(|cell| !cell.is_empty())
+            .map_or(0, |last| last + 1);
+        used.max(width.min(self.cells.len()))
+    }
+}
+
+/// Reads the fields of a pay slip export from `reader`.
+///
+/// Every record holds a field name followed by its values, or with a
+/// transposed layout a period followed by the value of every field. Names
+/// may be quoted, lines starting with `#` and blank lines are skipped, and a
+/// first record whose values are not numbers is taken as a header naming
+/// the periods. Every field must have a value for each period, blank cells
+/// are handled according to `options.missing_values`. Errors point at
+/// `source`, the line and the column of the offending cell.
+pub fn read_items<R: Read>(
+    mut reader: R,
+    source: &str,
+    options: &LoaderOptions,
+) -> Result<Dataset, Error> {
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .map_err(|e| Error::io(source, e))?;
+
+    let delimiter = options
+        .delimiter
+        .unwrap_or_else(|| detect_delimiter(&content));
+    let number_format = &options
+        .number_format
+        .unwrap_or_else(|| NumberFormat::guess(delimiter));
+
+    let mut rows = read_rows(&content, delimiter, source)?;
+    let header = match rows.first() {
+        Some(first) if is_header(first, number_format) => Some(rows.remove(0)),
+        _ => None,
+    };
+
+    let transposed = match options.layout {
+        Layout::Rows => false,
+        Layout::Transposed => true,
+        Layout::Auto => header
+            .as_ref()
+            .is_some_and(|header| looks_transposed(header, &rows)),
+    };
+    let (periods, raw_items) = match (transposed, header) {
+        (false, header) => items_from_rows(header.as_ref(), &rows, number_format, source)?,
+        (true, Some(header)) => items_from_columns(&header, &rows, number_format, source)?,
+        (true, None) => {
+            return Err(Error::parse(
+                source,
+                None,
+                None,
+                "a transposed layout needs a header row naming the fields",
+            ));
+        }
+    };
+
+    fill_missing(periods, raw_items, options.missing_values, source)
+}
+
+fn fill_missing(
+    periods: Vec<String>,
+    raw_items: Vec<RawItem>,
+    missing_values: MissingValues,
+    source: &str,
+) -> Result<Dataset, Error> {
+    let mut dataset = Dataset {
+        periods,
+        ..Default::default()
+    };
+
+    for raw_item in raw_items {
+        let blanks: Vec<MissingValue> = raw_item
+            .values
+            .iter()
+            .zip(&raw_item.locations)
+            .enumerate()
+            .filter(|(_, (value, _))| value.is_none())
+            .map(|(period, (_, &(line, column)))| MissingValue {
+                field: raw_item.name.clone(),
+                period: dataset.periods[period].clone(),
+                line,
+                column,
+            })
+            .collect();
+
+        let fill = match (blanks.first(), missing_values) {
+            (None, _) => 0_f64,
+            (Some(blank), MissingValues::Error) => {
+                return Err(Error::parse(
+                    source,
+                    blank.line,
+                    blank.column,
+                    "empty value",
+                ));
+            }
+            (Some(_), MissingValues::Zero) => 0_f64,
+            (Some(_), MissingValues::Unknown) => f64::NAN,
+            (Some(_), MissingValues::Reject) => {
+                dataset.rejected.push(raw_item.name);
+                dataset.missing.extend(blanks);
+                continue;
+            }
+        };
+
+        dataset.missing.extend(blanks);
+        let (first_row, first_column) = raw_item.locations.first().copied().unwrap_or_default();
+        let (last_row, last_column) = raw_item.locations.last().copied().unwrap_or_default();
+        dataset.ranges.push(SheetRange {
+            first_row,
+            first_column,
+            last_row,
+            last_column,
+        });
+        dataset.items.push(Item {
+            name: raw_item.name,
+            values: raw_item.values.iter().map(|v| v.unwrap_or(fill)).collect(),
+        });
+    }
+
+    Ok(dataset)
+}
+
+fn read_rows(content: &str, delimiter: u8, source: &str) -> Result<Vec<Row>, Error> {
+    let mut csv_reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .comment(Some(COMMENT))
+        .trim(Trim::All)
+        .from_reader(content.as_bytes());
+
+    // the reader's own line count is off on CRLF files, count from offsets,
+    // skipping the terminator a record position may start at
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_at = |pos: &csv::Position| {
+        let start = content[pos.byte() as usize..]
+            .find(|c| c != '\r' && c != '\n')
+            .map_or(content.len(), |skip| pos.byte() as usize + skip);
+        line_starts.partition_point(|&s| s <= start)
+    };
+
+    csv_reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| match e.position() {
+                Some(pos) => Error::parse(source, line_at(pos) as u64, None, e.to_string()),
+                None => Error::parse(source, None, None, e.to_string()),
+            })?;
+
+            Ok(Row {
+                line: record.position().map_or(0, |pos| line_at(pos) as u64),
+                cells: record.iter().map(str::to_string).collect(),
+            })
+        })
+        .collect()
+}
+
+fn items_from_rows(
+    header: Option<&Row>,
+    rows: &[Row],
+    number_format: &NumberFormat,
+    source: &str,
+) -> Result<(Vec<String>, Vec<RawItem>), Error> {
+    let periods: Vec<String> = match (header, rows.first()) {
+        (Some(header), _) => {
+            let width = header.width(0);
+            for (column, period) in header.cells[..width].iter().enumerate().skip(1) {
+                if period.is_empty() {
+                    return Err(Error::parse(
+                        source,
+                        header.line,
+                        column + 1,
+                        "missing period name",
+                    ));
+                }
+            }
+            header.cells[1..width.max(1)].to_vec()
+        }
+        (None, Some(first)) => (1..first.width(0)).map(|p| p.to_string()).collect(),
+        (None, None) => Vec::new(),
+    };
+
+    let items = rows
+        .iter()
+        .map(|row| {
+            let name = row.cells.first().map(String::as_str).unwrap_or_default();
+            if name.is_empty() {
+                return Err(Error::parse(source, row.line, 1, "missing field name"));
+            }
+
+            let width = row.width(periods.len() + 1);
+            if width != periods.len() + 1 {
+                return Err(Error::parse(
+                    source,
+                    row.line,
+                    None,
+                    format!(
+                        "{} has {} values but there are {} periods",
+                        name,
+                        width.saturating_sub(1),
+                        periods.len()
+                    ),
+                ));
+            }
+
+            let values = row.cells[..width]
+                .iter()
+                .enumerate()
+                .skip(1)
+                .map(|(column, cell)| {
+                    parse_value(cell, number_format, source, row.line, column + 1)
+                })
+                .collect::<Result<_, _>>()?;
+
+            Ok(RawItem {
+                name: name.to_string(),
+                values,
+                locations: (2..=width).map(|column| (row.line, column)).collect(),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok((periods, items))
+}
+
+fn items_from_columns(
+    header: &Row,
+    rows: &[Row],
+    number_format: &NumberFormat,
+    source: &str,
+) -> Result<(Vec<String>, Vec<RawItem>), Error> {
+    let header_width = header.width(0);
+    let mut items = Vec::with_capacity(header_width.saturating_sub(1));
+    for (column, name) in header.cells[..header_width].iter().enumerate().skip(1) {
+        if name.is_empty() {
+            return Err(Error::parse(
+                source,
+                header.line,
+                column + 1,
+                "missing field name",
+            ));
+        }
+        items.push(RawItem {
+            name: name.clone(),
+            values: Vec::with_capacity(rows.len()),
+            locations: Vec::with_capacity(rows.len()),
+        });
+    }
+
+    let mut periods = Vec::with_capacity(rows.len());
+    for row in rows {
+        let period = row.cells.first().map(String::as_str).unwrap_or_default();
+        if period.is_empty() {
+            return Err(Error::parse(source, row.line, 1, "missing period name"));
+        }
+        periods.push(period.to_string());
+
+        if row.width(header_width) > header_width {
+            return Err(Error::parse(
+                source,
+                row.line,
+                header_width + 1,
+                "value without a field in the header",
+            ));
+        }
+        for (column, item) in items.iter_mut().enumerate() {
+            let cell = row
+                .cells
+                .get(column + 1)
+                .map(String::as_str)
+                .unwrap_or_default();
+            let value = parse_value(cell, number_format, source, row.line, column + 2)?;
+            item.values.push(value);
+            item.locations.push((row.line, column + 2));
+        }
+    }
+
+    Ok((periods, items))
+}
+
+/// Tells whether `cell` names a month, a quarter or an extra monthly
+/// payment, as the first column of a transposed export does.
+fn is_period_name(cell: &str) -> bool {
+    // en, it, de, fr and es abbreviations
+    const MONTHS: [&str; 25] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec", "gen",
+        "mag", "giu", "lug", "ago", "set", "ott", "dic", "mai", "okt", "dez", "ene", "abr",
+    ];
+    // 13th and 14th month payments
+    const EXTRA_MONTHS: [&str; 4] = ["13", "14", "tredicesima", "quattordicesima"];
+    let cell = cell.to_lowercase();
+    let prefix: String = cell.chars().take(3).collect();
+    let digits: String = cell.chars().take_while(char::is_ascii_digit).collect();
+    MONTHS.contains(&prefix.as_str())
+        || EXTRA_MONTHS.contains(&cell.as_str())
+        || (digits.len() == 2 && digits != cell && EXTRA_MONTHS.contains(&digits.as_str()))
+        || cell.chars().count() == 2 && cell.starts_with('q') && cell[1..].parse::<u8>().is_ok()
+}
+
+fn looks_transposed(header: &Row, rows: &[Row]) -> bool {
+    !header.cells.iter().skip(1).all(|cell| is_period_name(cell))
+        && !rows.is_empty()
+        && rows
+            .iter()
+            .all(|row| row.cells.first().is_some_and(|cell| is_period_name(cell)))
+}
+
+/// Reads a delimiter given by the user, a single character or `tab`.
+pub fn parse_delimiter(delimiter: &str) -> Option<u8> {
+    match delimiter {
+        "\\t" | "\t" | "tab" => Some(b'\t'),
+        d if d.len() == 1 => Some(d.as_bytes()[0]),
+        _ => None,
+    }
+}
+
+/// Picks the delimiter found the most times on every one of the first
+/// lines. Quoted text is not looked into.
+fn detect_delimiter(content: &str) -> u8 {
+    let lines: Vec<&str> = content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with(COMMENT as char))
+        .take(SNIFF_LINES)
+        .collect();
+
+    let count_unquoted = |line: &str, delimiter: u8| {
+        let mut quoted = false;
+        line.bytes()
+            .filter(|&b| {
+                if b == b'"' {
+                    quoted = !quoted;
+                }
+                !quoted && b == delimiter
+            })
+            .count()
+    };
+
+    DELIMITERS
+        .iter()
+        .filter_map(|&delimiter| {
+            let least = lines
+                .iter()
+                .map(|line| count_unquoted(line, delimiter))
+                .min()?;
+            (least > 0).then_some((delimiter, least))
+        })
+        .max_by_key(|&(_, least)| least)
+        .map_or(DELIMITERS[0], |(delimiter, _)| delimiter)
+}
+
+/// A first row naming periods, by name or with three or more consecutive
+/// numbers such as `Voce,1,2,3` or `Field,2022,2023,2024`. A data row with
+/// a typo in some of its values is not one, its values are reported.
+fn is_header(row: &Row, number_format: &NumberFormat) -> bool {
+    let mut labels = row.cells.iter().skip(1).filter(|cell| !cell.is_empty());
+    let named =
+        labels.clone().next().is_some() && labels.all(|cell| number_format.parse(cell).is_none());
+    let numbers: Option<Vec<i64>> = row.cells[..row.width(0)]
+        .iter()
+        .skip(1)
+        .map(|cell| cell.parse().ok())
+        .collect();
+    let numbered = row
+        .cells
+        .first()
+        .is_some_and(|cell| !cell.is_empty() && number_format.parse(cell).is_none())
+        && numbers.is_some_and(|numbers| {
+            numbers.len() > 2 && numbers.windows(2).all(|pair| pair[1] == pair[0] + 1)
+        });
+    named || numbered
+}
+
+/// Parses a cell, `None` when it is blank.
+fn parse_value(
+    cell: &str,
+    number_format: &NumberFormat,
+    source: &str,
+    line: u64,
+    column: usize,
+) -> Result<Option<f64>, Error> {
+    if cell.is_empty() {
+        return Ok(None);
+    }
+    number_format
+        .parse(cell)
+        .map(Some)
+        .ok_or_else(|| Error::parse(source, line, column, format!("invalid number {:?}", cell)))
+}
@@ -1,404 +1,653 @@
-use std::fmt::Display;
 use std::fs::File;
-use std::io::Read;
-
-mod combinedresult;
-mod item;
-mod masked_permutation;
-mod permutation;
-mod progress;
-mod singleresult;
-mod sorted_vec;
-mod utils;
-
-use combinedresult::CombinedResult;
-use item::Item;
-use masked_permutation::MaskedPermutation;
-use permutation::PermutationKey;
-use progress::Progress;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use singleresult::SingleResult;
-use sorted_vec::SortedVec;
-use std::collections::HashMap;
-use std::collections::hash_map::Entry;
+use std::io::{BufReader, BufWriter, IsTerminal, Write};
+use std::process::ExitCode;
+use std::sync::mpsc;
 use std::thread;
 
-impl Display for SortedVec<SingleResult> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "Values:")?;
-        for v in &self.data {
-            writeln!(f, "\t{}", v)?;
-        }
-        Ok(())
-    }
-}
-
-fn get_total_for_perm(permutation_sign: u32, permutation_select: u32, fields: &[Item]) -> f64 {
-    let mut total = 0_f64;
-    // for each field in the pay slip...
-    for (pay_field_n, pay_field) in fields.iter().enumerate() {
-        // determine if it should be selected
-        let select_field = ((permutation_select >> pay_field_n) & 1) != 0;
-        if !select_field {
-            continue;
+mod cli;
+mod http;
+mod interrupt;
+mod jobfile;
+mod repl;
+mod server;
+
+use cal_cu_lator::Error;
+use cal_cu_lator::combinedresult;
+use cal_cu_lator::output::{self, OutputFormat};
+use cal_cu_lator::ranking::Ranking;
+use cal_cu_lator::run::{STDIN, load_dataset, run_cu_solver};
+use cal_cu_lator::shard;
+use cal_cu_lator::sorted_vec::SortedVec;
+use cal_cu_lator::verify;
+use clap::Parser;
+use cli::{Cli, Command, InspectArgs, MergeArgs, SolveArgs, VerifyArgs};
+use jobfile::CombineSettings;
+use rayon::prelude::*;
+
+/// Runs every job on the rayon pool and writes the rankings, and unless
+/// `combined_only` the combined one, in `format`. NDJSON rankings are
+/// written as soon as their search is done, the combined one last.
+fn solve(args: &SolveArgs, combined_only: bool) -> Result<ExitCode, Error> {
+    let (jobs, mut options, format, output, mut combine) = match &args.job {
+        Some(path) => {
+            let job_file = jobfile::load(path)?;
+            let (jobs, options) = job_file.jobs().map_err(|e| e.in_file(path))?;
+            (
+                jobs,
+                options,
+                job_file.format,
+                job_file.output,
+                job_file.combine,
+            )
         }
-
-        // determine the sign
-        let make_field_negative = ((permutation_sign >> pay_field_n) & 1) == 0;
-        for month_amount in &pay_field.values {
-            let month_amount_with_sign = if make_field_negative {
-                month_amount * -1_f64
-            } else {
-                *month_amount
+        None => {
+            let (jobs, options) = args.jobs()?;
+            let combine = CombineSettings {
+                enabled: true,
+                rank_size: args.combined_rank_size,
             };
-            total += month_amount_with_sign;
+            (jobs, options, args.format, args.output.clone(), combine)
         }
+    };
+    if combined_only {
+        if jobs.len() < 2 {
+            return Err(Error::InvalidOption(
+                "combine needs at least two searches".to_string(),
+            ));
+        }
+        combine.enabled = true;
+    }
+    if let Some(shard) = args.shard {
+        if combined_only {
+            return Err(Error::InvalidOption(
+                "shards are combined by merge".to_string(),
+            ));
+        }
+        options.control = options.control.shard(shard);
     }
-    total
-}
 
-fn find_permutation(
-    fields: &[Item],
-    goal: f64,
-    rank_size: usize,
-) -> Result<SortedVec<SingleResult>, String> {
-    let num_fields = fields.len();
-    if num_fields > 31 {
-        return Err("Too many fields (max 31 supported)".to_string());
+    // stdin can only be read once
+    if jobs.iter().filter(|job| job.file == STDIN).count() > 1 {
+        return Err(Error::InvalidOption(
+            "stdin (-) can only be read by one search".to_string(),
+        ));
     }
-    let all_fields_mask = (1 << num_fields) - 1;
-    println!(
-        "Using {:b} mask to compute permutations on {} fields",
-        all_fields_mask, num_fields
-    );
 
-    let field_names: Vec<String> = fields.iter().map(|i| i.name.clone()).collect();
+    let (mut out, output_name) = create_output(output.as_deref())?;
+    let write_error = |e| Error::io(output_name, e);
 
-    let progress = Progress::new(all_fields_mask);
+    for job in &jobs {
+        options.control.notice(format!(
+            "Reading from: {:?}\n\nRunning with goal: {:?}\nrank_size: {}\n",
+            job.file, job.goals, job.rank_size
+        ));
+    }
+    interrupt::install();
+    let _watch = interrupt::watch(&options.control);
+    // NDJSON rankings are written as soon as their search is done
+    let stream = format == OutputFormat::Ndjson && !combined_only && args.shard.is_none();
+    let mut rankings: Vec<Option<Ranking>> = jobs.iter().map(|_| None).collect();
+    let mut failure = None;
+    thread::scope(|scope| -> Result<(), Error> {
+        let (sender, receiver) = mpsc::channel();
+        // the searches share the rayon pool with the permutations they run
+        scope.spawn(|| {
+            jobs.par_iter()
+                .enumerate()
+                .for_each_with(sender, |sender, (n, job)| {
+                    let ranking =
+                        run_cu_solver(job.file.as_str(), &job.goals, job.rank_size, &options);
+                    let _ = sender.send((n, ranking));
+                });
+        });
+        for (n, ranking) in receiver {
+            match ranking {
+                Ok(ranking) => {
+                    if stream {
+                        let lines =
+                            output::ranking_lines(&ranking).map_err(|e| write_error(e.into()))?;
+                        for line in lines {
+                            writeln!(out, "{}", line).map_err(write_error)?;
+                        }
+                        out.flush().map_err(write_error)?;
+                    }
+                    rankings[n] = Some(ranking);
+                }
+                Err(err) => {
+                    // the other searches are of no use anymore
+                    options.control.cancel();
+                    failure.get_or_insert(err);
+                }
+            }
+        }
+        Ok(())
+    })?;
+    if let Some(err) = failure {
+        return Err(err);
+    }
+    let mut file_process_results: Vec<Ranking> = rankings.into_iter().flatten().collect();
 
-    let rank = (1_u32..=all_fields_mask)
-        .into_par_iter()
-        .flat_map_iter(|permutation_select| {
-            let progress = progress.clone();
-            progress.tick();
-            MaskedPermutation::from(permutation_select)
-                .map(move |permutation_sign| (permutation_select, permutation_sign))
-        })
-        .map(|(permutation_select, permutation_sign)| {
-            let perm_total = get_total_for_perm(permutation_sign, permutation_select, fields);
-            let err = perm_total - goal;
-            let diff = f64::abs(err);
-            SingleResult::new(
-                field_names.clone(),
-                permutation_sign,
-                permutation_select,
-                all_fields_mask,
-                diff,
-                err,
-            )
-        })
-        .fold(
-            // This closure is called once per thread to produce a brand-new accumulator:
-            || SortedVec::new(rank_size),
-            |mut acc, single_result| {
-                acc.insert_ordered(single_result);
-                acc
-            },
-        )
-        .reduce_with(SortedVec::merged)
-        .unwrap();
+    if let Some(shard) = args.shard {
+        return shard::write(&mut out, shard, file_process_results)
+            .and_then(|_| out.flush())
+            .map(|_| ExitCode::SUCCESS)
+            .map_err(write_error);
+    }
+
+    // with a single file there is nothing to combine
+    let sorted_combined_results = if combine.enabled && file_process_results.len() > 1 {
+        combinedresult::combine(&file_process_results, combine.rank_size)
+    } else {
+        SortedVec::new(combine.rank_size)
+    };
+    if combined_only || stream {
+        file_process_results.clear();
+    }
 
-    Ok(rank)
+    output::write_report(
+        &mut out,
+        format,
+        &file_process_results,
+        &sorted_combined_results,
+    )
+    .and_then(|_| out.flush())
+    .map_err(write_error)?;
+    Ok(ExitCode::SUCCESS)
 }
 
-fn run_cu_solver(
-    filename: &str,
-    goal: f64,
-    rank_size: usize,
-) -> Result<SortedVec<SingleResult>, String> {
-    // Build the CSV reader and iterate over each record.
-    let mut file_reader = File::open(filename).expect("not a valid file path");
-
-    let mut file_content = String::new();
-    let _ = file_reader.read_to_string(&mut file_content);
-
-    let items: Vec<Item> = file_content
-        .lines()
-        .map(|line| {
-            let mut columns = line.split(",");
-            let field_name = columns.next().unwrap();
-            let values = columns.map(|c| c.parse::<f64>().unwrap()).collect();
-            Item {
-                name: field_name.to_string(),
-                values,
-            }
+/// Merges the partial rankings of the shards of a run and writes them as
+/// `solve` would have.
+fn merge(args: &MergeArgs) -> Result<ExitCode, Error> {
+    let files = args
+        .files
+        .iter()
+        .map(|path| {
+            let file = File::open(path).map_err(|e| Error::io(path, e))?;
+            shard::read(BufReader::new(file), path)
         })
+        .collect::<Result<Vec<_>, _>>()?;
+    let missing: Vec<String> = shard::missing(&files)
+        .iter()
+        .map(|shard| shard.to_string())
         .collect();
+    if !missing.is_empty() {
+        eprintln!(
+            "Missing shards {}, rankings are partial",
+            missing.join(", ")
+        );
+    }
+    let rankings = shard::merge(files)?;
+
+    let combined = if rankings.len() > 1 {
+        combinedresult::combine(&rankings, args.combined_rank_size)
+    } else {
+        SortedVec::new(args.combined_rank_size)
+    };
+    let (mut out, output_name) = create_output(args.output.as_deref())?;
+    output::write_report(&mut out, args.format, &rankings, &combined)
+        .and_then(|_| out.flush())
+        .map_err(|e| Error::io(output_name, e))?;
+    Ok(ExitCode::SUCCESS)
+}
 
-    let perm_found = find_permutation(&items, goal, rank_size)?;
-    Ok(perm_found)
+/// Results go to stdout unless told otherwise, diagnostics always to stderr.
+fn create_output(output: Option<&str>) -> Result<(Box<dyn Write>, &str), Error> {
+    Ok(match output {
+        None | Some(STDIN) => (Box::new(std::io::stdout().lock()), "<stdout>"),
+        Some(path) => (
+            Box::new(BufWriter::new(
+                File::create(path).map_err(|e| Error::io(path, e))?,
+            )),
+            path,
+        ),
+    })
 }
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
+/// Prints the total of a formula against the goal, failing when it is
+/// farther than the tolerance.
+fn verify(args: &VerifyArgs) -> Result<ExitCode, Error> {
+    let dataset = load_dataset(&args.file, &args.loader.options())?;
+    let verification =
+        verify::verify_formula(&args.formula, &dataset.items, args.goal, args.tolerance)
+            .map_err(|e| e.in_file(&args.file))?;
+
+    println!("total: {}", verification.total);
+    println!("goal: {}", verification.goal);
+    println!("difference: {}", verification.difference);
+    if verification.matches {
+        println!("match");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("no match");
+        Ok(ExitCode::FAILURE)
+    }
+}
 
-    let default_name = "cal-cu-lator".to_string();
-    let program = args.first().unwrap_or(&default_name);
-    let errmsg =
-        "Usage: {} file_path_1.csv goal_1 rank_size_1  [file_path_2.csv goal_2 rank_size_2 ...]";
+/// Prints what was read from a file.
+fn inspect(args: &InspectArgs) -> Result<ExitCode, Error> {
+    let dataset = load_dataset(&args.file, &args.loader.options())?;
 
-    let expected_args = 3;
-    if (args.len() - 1) % expected_args != 0 {
-        panic!("{} {}", errmsg, program);
+    println!(
+        "periods ({}): {}",
+        dataset.periods.len(),
+        dataset.periods.join(", ")
+    );
+    println!("fields ({}):", dataset.items.len());
+    for (n, item) in dataset.items.iter().enumerate() {
+        let range = dataset
+            .ranges
+            .get(n)
+            .map_or_else(String::new, |r| format!(" ({})", r));
+        println!("\t{}{}: total {}", item.name, range, item.total());
     }
-
-    let mut thread_handles = vec![];
-    let mut index = 0;
-    loop {
-        index += 1;
-        if args.get(index).is_none() {
-            break;
+    if !dataset.missing.is_empty() {
+        println!("blank cells ({}):", dataset.missing.len());
+        for missing in &dataset.missing {
+            println!("\t{}", missing);
         }
-
-        let file = args.get(index).unwrap().clone();
-        index += 1;
-        let goal: f64 = str::parse(args.get(index).unwrap())
-            .unwrap_or_else(|_| panic!("{} {}", errmsg, program));
-        index += 1;
-        let rank_size: usize = str::parse(args.get(index).unwrap_or(&"10".to_string()))
-            .unwrap_or_else(|_| panic!("{} {}", errmsg, program));
-
-        println!(
-            "Reading from: {:?}\n\nRunning with goal: {}\nrank_size: {}\n\n",
-            file, goal, rank_size
-        );
-        thread_handles.push(thread::spawn(move || {
-            run_cu_solver(file.as_str(), goal, rank_size)
-        }));
     }
-
-    let mut file_process_results = Vec::new();
-    for handle in thread_handles {
-        match handle.join().unwrap() {
-            Ok(perm_rank) => file_process_results.push(perm_rank),
-            Err(err) => panic!("error running {}: {}", program, err),
-        };
+    if !dataset.rejected.is_empty() {
+        println!("rejected fields: {}", dataset.rejected.join(", "));
     }
+    Ok(ExitCode::SUCCESS)
+}
 
-    let mut combined_results: HashMap<PermutationKey, CombinedResult> = HashMap::new();
-
-    for res in &file_process_results {
-        // join results into combined results
-        for candidate in &res.data {
-            let combined_result_key: PermutationKey = candidate.get_own_key();
+fn main() -> ExitCode {
+    let cli = Cli::parse();
 
-            match combined_results.entry(combined_result_key) {
-                Entry::Occupied(mut entry) => {
-                    entry.get_mut().push_diff(candidate.diff);
-                }
-                Entry::Vacant(entry) => {
-                    let mut comb_res = CombinedResult::new(
-                        candidate.field_names.clone(),
-                        candidate.permutation_sign,
-                        candidate.permutation_select,
-                    );
-                    comb_res.push_diff(candidate.diff);
-                    entry.insert(comb_res);
-                }
-            }
+    let result = match &cli.command {
+        Command::Solve(args) => solve(args, false),
+        Command::Combine(args) => solve(args, true),
+        Command::Verify(args) => verify(args),
+        Command::Inspect(args) => inspect(args),
+        Command::Serve => {
+            server::serve(std::io::stdin().lock(), std::io::stdout()).map(|()| ExitCode::SUCCESS)
         }
-        println!("\n\nhere is a result {}", res)
-    }
-
-    // TODO we can return before the aggregation above probably
-    if file_process_results.len() == 1 {
-        // nothing to combine
-        return;
-    }
+        Command::Repl(args) => {
+            let mut repl = repl::Repl::new(
+                args.solver.run_options(args.loader.options()),
+                args.rank_size,
+            );
+            start_repl(&mut repl, args.file.as_deref())
+        }
+        Command::Http(args) => {
+            http::serve(args.port, args.max_jobs, args.keep_jobs).map(|()| ExitCode::SUCCESS)
+        }
+        Command::Merge(args) => merge(args),
+    };
+    result.unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        exit_code(&err)
+    })
+}
 
-    // sort the combined results
-    let mut sorted_combined_results: SortedVec<CombinedResult> = SortedVec::new(10);
-    for cr in combined_results.into_values() {
-        sorted_combined_results.insert_ordered(cr);
+/// Loads `file` when given and hands stdin over to the shell.
+fn start_repl(repl: &mut repl::Repl, file: Option<&str>) -> Result<ExitCode, Error> {
+    interrupt::install();
+    let mut out = std::io::stdout().lock();
+    if let Some(file) = file {
+        repl.load(file, &mut out)?;
     }
+    let stdin = std::io::stdin();
+    let prompt = stdin.is_terminal();
+    repl.run(stdin.lock(), out, prompt)?;
+    Ok(ExitCode::SUCCESS)
+}
 
-    for scr in sorted_combined_results.data {
-        println!("combined results: {}", scr);
-    }
+/// Exit status for each kind of error, listed in `--help`.
+fn exit_code(err: &Error) -> ExitCode {
+    ExitCode::from(match err {
+        Error::InvalidOption(_) => 2,
+        Error::Io { .. } => 3,
+        Error::Parse { .. } => 4,
+        Error::TooManyFields { .. } => 5,
+        Error::InvalidGoal(_) => 6,
+        Error::EmptyInput(_) => 7,
+        Error::Cancelled => 130,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use cal_cu_lator::numberformat::NumberFormat;
+    use cal_cu_lator::run::Job;
+    use cal_cu_lator::solver::find_permutation;
+    use cal_cu_lator::{Item, Permutation};
 
     #[test]
-    fn test_find_permutation_empty_input() {
-        let empty_vec: Vec<Item> = Vec::new();
-        let result = find_permutation(&empty_vec, 1000.0, 5);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().data.len(), 0);
+    fn test_job_file_with_constraints() {
+        let job_file: jobfile::JobFile = toml::from_str(
+            r#"
+            rank_size = 3
+
+            [loader]
+            locale = "it"
+            delimiter = ";"
+
+            [constraints]
+            pinned = ["AAAAA"]
+            excluded = ["CCCCC"]
+
+            [combine]
+            enabled = false
+
+            [[files]]
+            path = "a.csv"
+            goals = [100.5, 200]
+
+            [[files]]
+            path = "b.csv"
+            goals = [50]
+            rank_size = 5
+            "#,
+        )
+        .unwrap();
+        assert!(!job_file.combine.enabled);
+
+        let (jobs, options) = job_file.jobs().unwrap();
+        assert_eq!(
+            jobs,
+            [
+                Job {
+                    file: "a.csv".to_string(),
+                    goals: vec![100.5],
+                    rank_size: 3,
+                },
+                Job {
+                    file: "a.csv".to_string(),
+                    goals: vec![200.0],
+                    rank_size: 3,
+                },
+                Job {
+                    file: "b.csv".to_string(),
+                    goals: vec![50.0],
+                    rank_size: 5,
+                },
+            ]
+        );
+        assert_eq!(options.loader.delimiter, Some(b';'));
+
+        let items = vec![
+            Item {
+                name: "AAAAA".to_string(),
+                values: vec![1.0],
+            },
+            Item {
+                name: "BBBBB".to_string(),
+                values: vec![2.0],
+            },
+            Item {
+                name: "CCCCC".to_string(),
+                values: vec![3.0],
+            },
+        ];
+        let items = options.constraints.exclude(items).unwrap();
+        assert_eq!(items.len(), 2);
+        let pinned_mask = options.constraints.pinned_mask(&items).unwrap();
+        assert_eq!(pinned_mask, 0b1);
+
+        // the closest result without AAAAA would be BBBBB alone
+        let results = find_permutation(&items, 2.0, 5, pinned_mask).unwrap();
+        assert!(!results.data.is_empty());
+        for result in &results.data {
+            assert_ne!(result.get_signs()[0], 0);
+        }
     }
 
     #[test]
-    fn test_find_permutation_from_input_file() {
-        let filename = "test_data.csv";
-        let goal = 58200.23;
-        let rank_size = 10;
-        let p_rank_result = run_cu_solver(filename, goal, rank_size);
-        assert!(p_rank_result.is_ok());
-        let rank = p_rank_result.unwrap();
-
-        let descriptions = vec![
-            "AAAAA".to_string(),
-            "BBBBB".to_string(),
-            "CCCCC".to_string(),
-            "DDDDD".to_string(),
-            "EEEEE".to_string(),
-            "FFFFF".to_string(),
-            "ADDED".to_string(),
-        ];
+    fn test_command_line() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "cal-cu-lator",
+            "solve",
+            "--locale",
+            "it",
+            "--pin",
+            "AAAAA,BBBBB",
+            "a.csv",
+            "100.5",
+            "3",
+        ])
+        .unwrap();
+        let Command::Solve(args) = cli.command else {
+            panic!("expected solve");
+        };
+        let (jobs, options) = args.jobs().unwrap();
+        assert_eq!(
+            jobs,
+            [Job {
+                file: "a.csv".to_string(),
+                goals: vec![100.5],
+                rank_size: 3,
+            }]
+        );
+        assert_eq!(options.constraints.pinned, ["AAAAA", "BBBBB"]);
+        assert_eq!(
+            options.loader.number_format,
+            NumberFormat::from_locale("it")
+        );
 
-        let expected_rank = [
-            SingleResult::new(
-                descriptions.clone(),
-                0b111,
-                0b100111,
-                0b1111111,
-                23.399999999979627,
-                23.399999999979627,
-            ),
-            SingleResult::new(
-                descriptions.clone(),
-                0b1100111,
-                0b1111111,
-                0b1111111,
-                25.62999999998283,
-                -25.62999999998283,
-            ),
-            SingleResult::new(
-                descriptions.clone(),
-                0b10,
-                0b10110,
-                0b1111111,
-                29.949999999989814,
-                29.949999999989814,
-            ),
-            SingleResult::new(
-                descriptions.clone(),
-                0b1110110,
-                0b1111110,
-                0b1111111,
-                45.250000000007276,
-                45.250000000007276,
-            ),
-            SingleResult::new(
-                descriptions.clone(),
-                0b10,
-                0b111,
-                0b1111111,
-                82.5800000000163,
-                -82.5800000000163,
-            ),
-            SingleResult::new(
-                descriptions.clone(),
-                0b10110,
-                0b110110,
-                0b1111111,
-                89.13000000002648,
-                -89.13000000002648,
-            ),
-            SingleResult::new(
-                descriptions.clone(),
-                0b10010,
-                0b10111,
-                0b111111,
-                100.82999999997992,
-                100.82999999997992,
-            ),
-            SingleResult::new(
-                descriptions.clone(),
-                0b1100110,
-                0b1101110,
-                0b1111111,
-                138.15999999998894,
-                -138.15999999998894,
-            ),
-            SingleResult::new(
-                descriptions.clone(),
-                0b1100111,
-                0b1101111,
-                0b1111111,
-                157.7800000000134,
-                157.7800000000134,
-            ),
-            SingleResult::new(
-                descriptions.clone(),
-                0b111,
-                0b110111,
-                0b1111111,
-                160.0100000000166,
-                -160.0100000000166,
-            ),
-        ];
+        let cli = Cli::try_parse_from(["cal-cu-lator", "solve", "a.csv", "100"]).unwrap();
+        let Command::Solve(args) = cli.command else {
+            panic!("expected solve");
+        };
+        assert!(args.jobs().is_err());
+        assert!(Cli::try_parse_from(["cal-cu-lator", "solve", "--locale", "xx", "a.csv"]).is_err());
+        assert!(Cli::try_parse_from(["cal-cu-lator", "solve"]).is_err());
+
+        // negative goals are goals, not flags
+        let cli = Cli::try_parse_from(["cal-cu-lator", "solve", "a.csv", "-100", "2"]).unwrap();
+        let Command::Solve(args) = cli.command else {
+            panic!("expected solve");
+        };
+        assert_eq!(args.jobs().unwrap().0[0].goals, [-100.0]);
+        let cli =
+            Cli::try_parse_from(["cal-cu-lator", "verify", "a.csv", "-5", "+ AAAAA"]).unwrap();
+        let Command::Verify(args) = cli.command else {
+            panic!("expected verify");
+        };
+        assert_eq!(args.goal, -5.0);
 
-        rank.data.iter().enumerate().for_each(|(i, res)| {
-            let b = expected_rank.get(i).unwrap();
-            assert_eq!(res, b);
-        });
+        // a job file sets these itself
+        for flag in [["--format", "json"], ["--pin", "ZZZZZ"], ["--locale", "it"]] {
+            let args = ["cal-cu-lator", "solve", "--job", "j.toml", flag[0], flag[1]];
+            assert!(Cli::try_parse_from(args).is_err());
+        }
+        assert!(Cli::try_parse_from(["cal-cu-lator", "solve", "--job", "j.toml"]).is_ok());
     }
 
     #[test]
-    fn test_find_permutation_from_larger_input_file() {
-        let filename = "test_data_larger.csv";
-        let goal = 3110.76;
-        let rank_size = 3;
-        let p_rank_result = run_cu_solver(filename, goal, rank_size);
-        assert!(p_rank_result.is_ok());
-        let rank = p_rank_result.unwrap();
-
-        let descriptions = vec![
-            "AAAAA".to_string(),
-            "BBBBB".to_string(),
-            "CCCCC".to_string(),
-            "DDDDD".to_string(),
-            "EEEEE".to_string(),
-            "FFFFF".to_string(),
-            "GGGGG".to_string(),
-            "HHHHH".to_string(),
-            "IIIII".to_string(),
-            "JJJJJ".to_string(),
-        ];
+    fn test_server() {
+        #[derive(Clone, Default)]
+        struct Output(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+        impl Write for Output {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
 
-        let expected_rank = [
-            SingleResult::new(
-                descriptions.clone(),
-                0b10010110,
-                0b1010111110,
-                0b1111111111,
-                0.0000000000004547473508864641,
-                -0.0000000000004547473508864641,
-            ),
-            SingleResult::new(
-                descriptions.clone(),
-                0b11000000,
-                0b111110001,
-                0b111111111,
-                0.15999999999939973,
-                0.15999999999939973,
-            ),
-            SingleResult::new(
-                descriptions.clone(),
-                0b1010000,
-                0b1101011100,
-                0b1111111111,
-                0.3400000000001455,
-                0.3400000000001455,
-            ),
-        ];
+        let requests = [
+            r#"{"id": 1, "command": "load", "name": "d", "content": "AAAAA,1\nBBBBB,2\nCCCCC,4\n"}"#,
+            r#"{"id": 2, "command": "verify", "dataset": "d", "goal": 5, "formula": "AAAAA + CCCCC"}"#,
+            r#"{"id": 3, "command": "solve", "dataset": "d", "goal": 3, "rank_size": 2}"#,
+            r#"{"id": 4, "command": "solve", "dataset": "x", "goal": 3}"#,
+            r#"{"id": 5, "command": "frobnicate"}"#,
+            r#"{"id": 6, "command": "load", "path": "-"}"#,
+            "not json",
+        ]
+        .join("\n");
+        let output = Output::default();
+        server::serve(requests.as_bytes(), output.clone()).unwrap();
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let responses: Vec<serde_json::Value> = output
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .filter(|r: &serde_json::Value| r["type"] != "progress")
+            .collect();
+        let response = |id: serde_json::Value| {
+            responses
+                .iter()
+                .find(|r| r["id"] == id)
+                .unwrap_or_else(|| panic!("no response to {}", id))
+        };
 
-        rank.data.iter().enumerate().for_each(|(i, res)| {
-            let b = expected_rank.get(i).unwrap();
-            assert_eq!(res, b);
-        });
+        assert_eq!(response(1.into())["type"], "loaded");
+        assert_eq!(response(1.into())["fields"].as_array().unwrap().len(), 3);
+        assert_eq!(response(2.into())["type"], "verification");
+        assert_eq!(response(2.into())["matches"], true);
+        let ranking = response(3.into());
+        assert_eq!(ranking["type"], "ranking");
+        assert_eq!(ranking["results"][0]["diff"], 0.0);
+        assert_eq!(response(4.into())["kind"], "invalid_option");
+        assert_eq!(response(5.into())["kind"], "parse");
+        assert_eq!(response(6.into())["kind"], "invalid_option");
+        assert_eq!(response(serde_json::Value::Null)["kind"], "parse");
+    }
+
+    #[test]
+    fn test_http_api() {
+        let api = http::Api::new(1, 2).unwrap();
+        assert!(http::Api::new(0, 2).is_err());
+        let state = |id: u64| api.route("GET", &format!("/jobs/{}", id), "").1;
+        let wait = |id: u64| loop {
+            let status = state(id);
+            if status["state"] != "queued" && status["state"] != "running" {
+                break status;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        };
+        let submit = |dataset: &str| {
+            let request = serde_json::json!({ "dataset": dataset, "goal": 3 });
+            let (status, job) = api.route("POST", "/jobs", &request.to_string());
+            assert_eq!(status, 202);
+            job["job"].as_u64().unwrap()
+        };
+
+        let (status, loaded) = api.route(
+            "POST",
+            "/datasets/q%201?delimiter=%3B",
+            "AAAAA;1\nBBBBB;2\nCCCCC;4\n",
+        );
+        assert_eq!(status, 201);
+        assert_eq!(loaded["dataset"], "q 1");
+        assert_eq!(
+            api.route("GET", "/datasets", "").1,
+            serde_json::json!(["q 1"])
+        );
+
+        // a job waits in line while a search too long to finish runs
+        let fields: String = (1..=20).map(|i| format!("F{},{}.5\n", i, i)).collect();
+        assert_eq!(api.route("POST", "/datasets/long", &fields).0, 201);
+        let long = submit("long");
+        let queued = submit("q 1");
+        assert_eq!(state(queued)["state"], "queued");
+        assert_eq!(api.route("DELETE", &format!("/jobs/{}", long), "").0, 200);
+        assert_eq!(wait(long)["state"], "cancelled");
+
+        for id in [queued, submit("q 1")] {
+            let status = wait(id);
+            assert_eq!(status["state"], "done");
+            assert_eq!(status["percent"], 100);
+            let (status, ranking) = api.route("GET", &format!("/jobs/{}/results", id), "");
+            assert_eq!(status, 200);
+            assert_eq!(ranking["results"][0]["diff"], 0.0);
+            assert_eq!(api.route("DELETE", &format!("/jobs/{}", id), "").0, 200);
+            assert_eq!(api.route("GET", &format!("/jobs/{}", id), "").0, 404);
+        }
+
+        // only the last two finished jobs are kept once another one comes
+        let finished: Vec<u64> = (0..3).map(|_| submit("q 1")).collect();
+        for &id in &finished {
+            wait(id);
+        }
+        let last = submit("q 1");
+        wait(last);
+        assert_eq!(api.route("GET", &format!("/jobs/{}", long), "").0, 404);
+        assert_eq!(
+            api.route("GET", &format!("/jobs/{}", finished[0]), "").0,
+            404
+        );
+        assert_eq!(state(finished[1])["state"], "done");
+
+        let (status, error) = api.route("POST", "/jobs", r#"{"dataset": "x", "goal": 3}"#);
+        assert_eq!(status, 400);
+        assert_eq!(error["kind"], "invalid_option");
+        assert_eq!(api.route("POST", "/jobs", "{").1["kind"], "parse");
+        let request = r#"{"dataset": "q 1", "goal": 3, "solver": {"checkpoint_dir": "/tmp"}}"#;
+        let (status, error) = api.route("POST", "/jobs", request);
+        assert_eq!(status, 400);
+        assert_eq!(error["kind"], "invalid_option");
+        assert_eq!(api.route("GET", "/nowhere", "").0, 404);
+    }
+
+    #[test]
+    fn test_repl() {
+        let export = std::env::temp_dir().join("cal_cu_lator_repl_test.json");
+        let script = format!(
+            "load test_data.csv\n\
+             solve\n\
+             goal 58200.23\n\
+             pin AAAAA\n\
+             exclude CCCCC, ZZZZZ\n\
+             exclude CCCCC\n\
+             fields\n\
+             solve\n\
+             verify 1\n\
+             export {} json\n\
+             quit\n\
+             show\n",
+            export.display()
+        );
+        let mut repl = repl::Repl::new(Default::default(), 3);
+        let mut out = Vec::new();
+        repl.run(script.as_bytes(), &mut out, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("error: set a goal first"));
+        assert!(out.contains("error: field ZZZZZ not found"));
+        assert!(out.contains("AAAAA (pinned)\n"));
+        assert!(out.contains("CCCCC (excluded)\n"));
+        assert!(out.contains("3 results"));
+        // results after quit are not shown
+        assert_eq!(out.matches("  1. error").count(), 1);
+        let first = out.lines().find(|l| l.starts_with("  1. error")).unwrap();
+        assert!(first.contains("+ AAAAA"));
+        assert!(!first.contains("CCCCC"));
+        assert!(out.contains("\ntotal: "));
+
+        let exported: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&export).unwrap()).unwrap();
+        assert_eq!(
+            exported["rankings"][0]["results"].as_array().unwrap().len(),
+            3
+        );
+        std::fs::remove_file(export).unwrap();
+
+        // collapsed fields are verified through their first member
+        let input = std::env::temp_dir().join("cal_cu_lator_repl_test.csv");
+        std::fs::write(&input, "AAAAA,1,2\nBBBBB,1,2\nCCCCC,4,4\n").unwrap();
+        let script = format!("load {}\ngoal 11\nsolve\nverify 1\n", input.display());
+        let options = cal_cu_lator::run::RunOptions {
+            equivalence_tolerance: Some(0.001),
+            ..Default::default()
+        };
+        let mut repl = repl::Repl::new(options, 3);
+        let mut out = Vec::new();
+        repl.run(script.as_bytes(), &mut out, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("+ AAAAA | BBBBB + CCCCC\ntotal: 11"),
+            "{}",
+            out
+        );
+        assert!(out.contains("\nmatch\n"));
+        std::fs::remove_file(input).unwrap();
     }
 }
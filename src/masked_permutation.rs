@@ -11,7 +11,7 @@ impl MaskedPermutation {
 
 impl From<u32> for MaskedPermutation {
   fn from(mask: u32) -> Self {
-      MaskedPermutation { mask, start: 0 }
+      MaskedPermutation::new(mask)
   }
 }
 
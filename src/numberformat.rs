@@ -0,0 +1,100 @@
+/// How amounts are written in an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub decimal_separator: char,
+    pub thousands_separator: Option<char>,
+}
+
+const CURRENCY_SYMBOLS: [char; 5] = ['€', '$', '£', '¥', '₣'];
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            decimal_separator: '.',
+            thousands_separator: Some(','),
+        }
+    }
+}
+
+impl NumberFormat {
+    /// The format an export delimited by `delimiter` most likely uses:
+    /// spreadsheets only pick `;` where the comma is the decimal separator.
+    pub fn guess(delimiter: u8) -> Self {
+        match delimiter {
+            b';' => NumberFormat {
+                decimal_separator: ',',
+                thousands_separator: Some('.'),
+            },
+            _ => NumberFormat::default(),
+        }
+    }
+
+    /// Returns the format used by a locale, e.g. `it` for `1.694,46`.
+    pub fn from_locale(locale: &str) -> Option<Self> {
+        let (decimal_separator, thousands_separator) = match locale {
+            "en" | "us" | "uk" => ('.', ','),
+            "it" | "de" | "es" | "nl" | "pt" => (',', '.'),
+            "fr" => (',', ' '),
+            "ch" => ('.', '\''),
+            _ => return None,
+        };
+        Some(NumberFormat {
+            decimal_separator,
+            thousands_separator: Some(thousands_separator),
+        })
+    }
+
+    /// Parses an amount, ignoring currency symbols and thousands separators
+    /// and reading `(12.50)` as `-12.50`.
+    ///
+    /// Thousands separators must group the digits by three, so that an
+    /// amount written in another format, like `1,5` or `1.694,46` for `en`,
+    /// is rejected instead of misread. `nan` and `inf` are not amounts.
+    pub fn parse(&self, cell: &str) -> Option<f64> {
+        let mut amount = cell.trim();
+        let negative = amount.starts_with('(') && amount.ends_with(')');
+        if negative {
+            amount = &amount[1..amount.len() - 1];
+        }
+
+        let mut normalized = String::with_capacity(amount.len());
+        // digits since the start or the last thousands separator
+        let mut group = 0;
+        let mut grouped = false;
+        let mut fraction = false;
+        for c in amount.chars() {
+            if c == self.decimal_separator {
+                if grouped && group != 3 {
+                    return None;
+                }
+                fraction = true;
+                grouped = false;
+                normalized.push('.');
+            } else if Some(c) == self.thousands_separator && !c.is_whitespace() {
+                // the first group may be shorter, the others have three digits
+                if fraction || group == 0 || group > 3 || (grouped && group != 3) {
+                    return None;
+                }
+                grouped = true;
+                group = 0;
+            } else if CURRENCY_SYMBOLS.contains(&c)
+                // spaces, also the non-breaking ones spreadsheets like to use
+                || c.is_whitespace()
+            {
+                continue;
+            } else {
+                if c.is_ascii_digit() {
+                    group += 1;
+                }
+                normalized.push(c);
+            }
+        }
+        if grouped && group != 3 {
+            return None;
+        }
+
+        // "nan" and "inf" parse too, but no amount is either
+        let value = normalized.parse::<f64>().ok().filter(|v| v.is_finite())?;
+        Some(if negative { -value } else { value })
+    }
+}
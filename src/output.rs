@@ -0,0 +1,290 @@
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::combinedresult::CombinedResult;
+use crate::permutation::Permutation;
+use crate::ranking::Ranking;
+use crate::sorted_vec::SortedVec;
+
+/// How rankings are written to stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human readable, through `Display`.
+    #[default]
+    Text,
+    /// One JSON document once every file is done.
+    Json,
+    /// One JSON object per result.
+    Ndjson,
+    /// One CSV row per result, for spreadsheets.
+    Csv,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SelectedField<'a> {
+    pub name: &'a str,
+    /// `1` when the field is added, `-1` when it is subtracted.
+    pub sign: i8,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResultRecord<'a> {
+    /// 1-based position in the ranking.
+    pub rank: usize,
+    pub selected: Vec<SelectedField<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<f64>,
+    pub diff: f64,
+    /// Signed error, `total - goal` for single files.
+    pub error: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validation_error: Option<f64>,
+    /// Spreadsheet formula reproducing the total in the original file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formula: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RankingRecord<'a> {
+    pub file: &'a str,
+    pub goal: f64,
+    pub field_names: &'a [String],
+    pub results: Vec<ResultRecord<'a>>,
+    /// Only written when the search stopped before trying every formula.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report<'a> {
+    pub rankings: Vec<RankingRecord<'a>>,
+    /// Results shared by every file, empty for single file runs.
+    pub combined: Vec<ResultRecord<'a>>,
+}
+
+/// A line of NDJSON output, tagged with where the result comes from.
+#[derive(Debug, Serialize)]
+pub struct StreamRecord<'a> {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub goal: Option<f64>,
+    pub field_names: &'a [String],
+    #[serde(flatten)]
+    pub result: ResultRecord<'a>,
+}
+
+pub fn result_record<P: Permutation>(
+    rank: usize,
+    result: &P,
+    goal: Option<f64>,
+) -> ResultRecord<'_> {
+    let selected = result
+        .get_field_names()
+        .iter()
+        .zip(result.get_signs())
+        .filter(|(_, sign)| *sign != 0)
+        .map(|(name, sign)| SelectedField { name, sign })
+        .collect();
+
+    ResultRecord {
+        rank,
+        selected,
+        total: goal.map(|goal| goal + result.get_error()),
+        diff: result.get_diff(),
+        error: result.get_error(),
+        validation_error: result.get_validation_error(),
+        formula: None,
+    }
+}
+
+pub fn ranking_record(ranking: &Ranking) -> RankingRecord<'_> {
+    RankingRecord {
+        file: &ranking.file,
+        goal: ranking.goal,
+        field_names: &ranking.field_names,
+        partial: ranking.partial,
+        results: ranking
+            .results
+            .data
+            .iter()
+            .enumerate()
+            .map(|(i, r)| ResultRecord {
+                formula: ranking.formula(r),
+                ..result_record(i + 1, r, Some(ranking.goal))
+            })
+            .collect(),
+    }
+}
+
+pub fn combined_records(combined: &SortedVec<CombinedResult>) -> Vec<ResultRecord<'_>> {
+    combined
+        .data
+        .iter()
+        .enumerate()
+        .map(|(i, r)| result_record(i + 1, r, None))
+        .collect()
+}
+
+/// NDJSON lines for the results of one file.
+pub fn ranking_lines(ranking: &Ranking) -> Result<Vec<String>, serde_json::Error> {
+    ranking
+        .results
+        .data
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            serde_json::to_string(&StreamRecord {
+                kind: "result",
+                file: Some(&ranking.file),
+                goal: Some(ranking.goal),
+                field_names: &ranking.field_names,
+                result: ResultRecord {
+                    formula: ranking.formula(r),
+                    ..result_record(i + 1, r, Some(ranking.goal))
+                },
+            })
+        })
+        .collect()
+}
+
+/// NDJSON lines for the combined results.
+pub fn combined_lines(
+    combined: &SortedVec<CombinedResult>,
+) -> Result<Vec<String>, serde_json::Error> {
+    combined
+        .data
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            serde_json::to_string(&StreamRecord {
+                kind: "combined",
+                file: None,
+                goal: None,
+                field_names: r.get_field_names(),
+                result: result_record(i + 1, r, None),
+            })
+        })
+        .collect()
+}
+
+/// Writes every ranking, then the combined one, in `format`.
+pub fn write_report<W: Write>(
+    mut writer: W,
+    format: OutputFormat,
+    rankings: &[Ranking],
+    combined: &SortedVec<CombinedResult>,
+) -> std::io::Result<()> {
+    match format {
+        OutputFormat::Text => {
+            for ranking in rankings {
+                writeln!(writer, "\n\nhere is a result {}", ranking)?;
+            }
+            for result in &combined.data {
+                writeln!(writer, "combined results: {}", result)?;
+            }
+            Ok(())
+        }
+        OutputFormat::Json => {
+            let report = Report {
+                rankings: rankings.iter().map(ranking_record).collect(),
+                combined: combined_records(combined),
+            };
+            let json = serde_json::to_string_pretty(&report)?;
+            writeln!(writer, "{}", json)
+        }
+        OutputFormat::Ndjson => {
+            for ranking in rankings {
+                for line in ranking_lines(ranking)? {
+                    writeln!(writer, "{}", line)?;
+                }
+            }
+            for line in combined_lines(combined)? {
+                writeln!(writer, "{}", line)?;
+            }
+            Ok(())
+        }
+        OutputFormat::Csv => write_csv(writer, rankings, combined).map_err(std::io::Error::other),
+    }
+}
+
+/// Writes every ranking, then the combined one, as a single CSV table.
+///
+/// There is a column per field of any file holding `1`, `-1` or `0`, so the
+/// rows of files with different fields line up. Combined results have no
+/// total nor goal.
+pub fn write_csv<W: Write>(
+    writer: W,
+    rankings: &[Ranking],
+    combined: &SortedVec<CombinedResult>,
+) -> Result<(), csv::Error> {
+    // combined results may be written without the rankings they come from
+    let mut field_names: Vec<&str> = Vec::new();
+    let combined_names = combined.data.iter().flat_map(|r| r.get_field_names());
+    for name in rankings
+        .iter()
+        .flat_map(|r| &r.field_names)
+        .chain(combined_names)
+    {
+        if !field_names.contains(&name.as_str()) {
+            field_names.push(name);
+        }
+    }
+
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    let mut header = vec!["source", "rank"];
+    header.extend(&field_names);
+    header.extend(["total", "goal", "error", "diff"]);
+    csv_writer.write_record(&header)?;
+
+    for ranking in rankings {
+        for (i, result) in ranking.results.data.iter().enumerate() {
+            csv_writer.write_record(csv_row(
+                &ranking.file,
+                i + 1,
+                result,
+                Some(ranking.goal),
+                &field_names,
+            ))?;
+        }
+    }
+    for (i, result) in combined.data.iter().enumerate() {
+        csv_writer.write_record(csv_row("combined", i + 1, result, None, &field_names))?;
+    }
+
+    csv_writer.flush()?;
+    Ok(())
+}
+
+fn csv_row<P: Permutation>(
+    source: &str,
+    rank: usize,
+    result: &P,
+    goal: Option<f64>,
+    field_names: &[&str],
+) -> Vec<String> {
+    let signs: Vec<(&String, i8)> = result
+        .get_field_names()
+        .iter()
+        .zip(result.get_signs())
+        .collect();
+
+    let mut record = vec![source.to_string(), rank.to_string()];
+    record.extend(field_names.iter().map(|name| {
+        signs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map_or(0, |(_, sign)| *sign)
+            .to_string()
+    }));
+    record.push(goal.map_or(String::new(), |g| (g + result.get_error()).to_string()));
+    record.push(goal.map_or(String::new(), |g| g.to_string()));
+    record.push(result.get_error().to_string());
+    record.push(result.get_diff().to_string());
+    record
+}
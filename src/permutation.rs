@@ -1,7 +1,7 @@
 use std::cmp::Ordering;
 use crate::utils;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PermutationKey(pub u32, pub u32);
 
 pub trait Permutation: std::fmt::Display {
@@ -12,6 +12,11 @@ pub trait Permutation: std::fmt::Display {
     fn get_error(&self) -> f64;
     fn get_diff(&self) -> f64;
 
+    /// Error on the held-out months, only set by cross-validation runs.
+    fn get_validation_error(&self) -> Option<f64> {
+        None
+    }
+
     fn fmt_display(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut permutation_sign = self.get_permutation_sign();
         let mut permutation_select = self.get_permutation_select();
@@ -33,6 +38,9 @@ pub trait Permutation: std::fmt::Display {
             rev_sele_perm,
             self.get_error()
         );
+        if let Some(validation_error) = self.get_validation_error() {
+            let _ = writeln!(f, "        validation error: {}", validation_error);
+        }
 
         let mut pretty_formula = String::new();
 
@@ -52,6 +60,23 @@ pub trait Permutation: std::fmt::Display {
         writeln!(f, "        pretty formula:{}", pretty_formula)
     }
 
+    /// Sign of every field: `1` added, `-1` subtracted, `0` not selected.
+    fn get_signs(&self) -> Vec<i8> {
+        let permutation_sign = self.get_permutation_sign();
+        let permutation_select = self.get_permutation_select();
+        (0..self.get_field_names().len())
+            .map(|n| {
+                if (permutation_select >> n) & 1 == 0 {
+                    0
+                } else if (permutation_sign >> n) & 1 == 1 {
+                    1
+                } else {
+                    -1
+                }
+            })
+            .collect()
+    }
+
     fn get_key(&self) -> PermutationKey {
         utils::get_perm_key(self.get_permutation_sign(), self.get_permutation_select(), self.get_mask())
     }
@@ -62,7 +87,8 @@ pub trait Permutation: std::fmt::Display {
         } else if self.get_diff() > other.get_diff() {
             Ordering::Greater
         } else {
-            Ordering::Equal
+            // ties go by formula, so a ranking doesn't depend on the search order
+            self.get_key().cmp(&other.get_key())
         }
     }
 
@@ -1,32 +1,68 @@
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::control::{ProgressReport, SearchControl};
+
+/// Counts the formulas evaluated, since the selections of more fields
+/// have exponentially more formulas.
 #[derive(Clone)]
 pub struct Progress {
-    total: u32,
-    current: Arc<AtomicU32>,
+    total: u64,
+    current: Arc<AtomicU64>,
+    skipped: u64,
+    started: Instant,
     last_percent: Arc<AtomicU32>,
+    control: SearchControl,
 }
 
 impl Progress {
-    pub fn new(total: u32) -> Self {
+    pub fn new(total: u64, control: SearchControl) -> Self {
         Progress {
             total,
-            current: Arc::new(AtomicU32::new(0)),
+            current: Arc::new(AtomicU64::new(0)),
+            skipped: 0,
+            started: Instant::now(),
             last_percent: Arc::new(AtomicU32::new(0)),
+            control,
         }
     }
 
-    pub fn tick(&self) {
-        let done = self.current.fetch_add(1, Ordering::Relaxed) + 1;
-        let percent = (done * 100 / self.total).min(100);
+    /// Counts `done` formulas as already evaluated, for resumed searches.
+    pub fn skip(&mut self, done: u64) {
+        self.skipped = done;
+        self.current.store(done, Ordering::Relaxed);
+    }
+
+    pub fn tick(&self, evaluations: u64) {
+        let done = self.current.fetch_add(evaluations, Ordering::Relaxed) + evaluations;
+        let percent = (done * 100 / self.total.max(1)).min(100) as u32;
         let last = self.last_percent.load(Ordering::Relaxed);
 
         if percent > last && self
                 .last_percent
                 .compare_exchange(last, percent, Ordering::Relaxed, Ordering::Relaxed)
                 .is_ok() {
-            println!("{}%", percent);
+            self.control.report(&self.report(percent, done));
+        }
+    }
+
+    fn report(&self, percent: u32, done: u64) -> ProgressReport {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        // resumed formulas were evaluated by an earlier run
+        let rate = if elapsed > 0.0 {
+            (done - self.skipped) as f64 / elapsed
+        } else {
+            0.0
+        };
+        // unknown, infinite or not a number, until something is evaluated
+        let eta = Duration::try_from_secs_f64(self.total.saturating_sub(done) as f64 / rate).ok();
+        ProgressReport {
+            percent,
+            evaluated: done,
+            total: self.total,
+            rate,
+            eta,
         }
     }
 }
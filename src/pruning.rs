@@ -0,0 +1,140 @@
+use std::fmt::Display;
+
+use crate::item::Item;
+
+const EPSILON: f64 = 1e-9;
+// coefficients come out of a float solve, cents leave some noise
+const COEFFICIENT_TOLERANCE: f64 = 1e-6;
+
+/// Why a field was left out of the search.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PruneReason {
+    /// Every value is zero, the field only duplicates results.
+    Zero,
+    /// The field equals a signed sum of the fields kept before it, the
+    /// formula for it is given in the same form as the pretty formula.
+    Combination(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pruned {
+    pub name: String,
+    pub reason: PruneReason,
+}
+
+impl Display for Pruned {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            PruneReason::Zero => write!(f, "{}: all zero", self.name),
+            PruneReason::Combination(formula) => write!(f, "{}: equals{}", self.name, formula),
+        }
+    }
+}
+
+/// Removes all-zero fields and, when `redundant` is set, fields that are an
+/// exact `+1`/`-1` combination of the fields kept before them: selecting such
+/// a field gives the same totals as selecting the combination itself.
+///
+/// Only `+1`/`-1` combinations are detected, a field that is twice another
+/// one is kept. All-zero fields kept with `zero` unset are never redundant.
+pub fn prune(fields: Vec<Item>, zero: bool, redundant: bool) -> (Vec<Item>, Vec<Pruned>) {
+    let mut kept: Vec<Item> = Vec::with_capacity(fields.len());
+    let mut pruned = Vec::new();
+    // indexes in `kept` of linearly independent fields
+    let mut basis: Vec<usize> = Vec::new();
+
+    for field in fields {
+        let all_zero = field.values.iter().all(|v| v.abs() <= EPSILON);
+        if zero && all_zero {
+            pruned.push(Pruned {
+                name: field.name,
+                reason: PruneReason::Zero,
+            });
+            continue;
+        }
+
+        // unknown values can't take part in a combination
+        if redundant && !all_zero && field.values.iter().all(|v| v.is_finite()) {
+            let basis_values: Vec<&[f64]> =
+                basis.iter().map(|&b| kept[b].values.as_slice()).collect();
+            match solve_combination(&basis_values, &field.values) {
+                Some(coefficients) if is_signed_sum(&coefficients) => {
+                    let mut formula = String::new();
+                    for (&b, c) in basis.iter().zip(&coefficients) {
+                        if c.abs() > 0.5 {
+                            let sign_str = if *c > 0.0 { "+" } else { "-" };
+                            formula.push_str(format!(" {} {}", sign_str, kept[b].name).as_str());
+                        }
+                    }
+                    pruned.push(Pruned {
+                        name: field.name,
+                        reason: PruneReason::Combination(formula),
+                    });
+                    continue;
+                }
+                Some(_) => {}
+                None => basis.push(kept.len()),
+            }
+        }
+
+        kept.push(field);
+    }
+
+    (kept, pruned)
+}
+
+fn is_signed_sum(coefficients: &[f64]) -> bool {
+    coefficients
+        .iter()
+        .all(|c| c.abs() <= COEFFICIENT_TOLERANCE || (c.abs() - 1.0).abs() <= COEFFICIENT_TOLERANCE)
+}
+
+/// Finds the coefficients expressing `target` as a linear combination of the
+/// linearly independent `basis` vectors, `None` if it is not in their span.
+fn solve_combination(basis: &[&[f64]], target: &[f64]) -> Option<Vec<f64>> {
+    let n = basis.len();
+    if basis.iter().any(|b| b.len() != target.len()) {
+        return None;
+    }
+    if n == 0 {
+        return target.iter().all(|v| v.abs() <= EPSILON).then(Vec::new);
+    }
+
+    // normal equations (B Bᵀ) c = B t, solved with partial pivoting
+    let dot = |l: &[f64], r: &[f64]| l.iter().zip(r).map(|(a, b)| a * b).sum::<f64>();
+    let mut system: Vec<Vec<f64>> = (0..n)
+        .map(|i| {
+            let mut row: Vec<f64> = (0..n).map(|j| dot(basis[i], basis[j])).collect();
+            row.push(dot(basis[i], target));
+            row
+        })
+        .collect();
+
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&a, &b| system[a][col].abs().total_cmp(&system[b][col].abs()))
+            .unwrap();
+        system.swap(col, pivot);
+        if system[col][col].abs() <= EPSILON {
+            return None;
+        }
+        let pivot_row = system[col].clone();
+        for (row, values) in system.iter_mut().enumerate() {
+            if row != col {
+                let factor = values[col] / pivot_row[col];
+                for (v, p) in values[col..].iter_mut().zip(&pivot_row[col..]) {
+                    *v -= factor * p;
+                }
+            }
+        }
+    }
+    let coefficients: Vec<f64> = (0..n).map(|i| system[i][n] / system[i][i]).collect();
+
+    // the least squares solution only counts if it reproduces the target
+    let scale = target.iter().fold(1_f64, |acc, v| acc.max(v.abs()));
+    let reproduces = target.iter().enumerate().all(|(m, t)| {
+        let value: f64 = basis.iter().zip(&coefficients).map(|(b, c)| b[m] * c).sum();
+        (value - t).abs() <= COEFFICIENT_TOLERANCE * scale
+    });
+    reproduces.then_some(coefficients)
+}
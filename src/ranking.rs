@@ -0,0 +1,48 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::formula::{self, SheetRange};
+use crate::singleresult::SingleResult;
+use crate::sorted_vec::SortedVec;
+
+/// The best formulas found for one file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Ranking {
+    pub file: String,
+    /// Names of the fields searched, after pruning and collapsing.
+    pub field_names: Vec<String>,
+    /// The total the errors are measured against, for cross-validation the
+    /// goal of the training periods.
+    pub goal: f64,
+    pub results: SortedVec<SingleResult>,
+    /// Where each field sits in the file, to build spreadsheet formulas.
+    pub ranges: Vec<Option<SheetRange>>,
+    /// The search stopped before trying every formula.
+    pub partial: bool,
+}
+
+impl Ranking {
+    /// Spreadsheet formula reproducing the total of a result over every
+    /// period of the original file.
+    pub fn formula(&self, result: &SingleResult) -> Option<String> {
+        formula::spreadsheet_formula(result, &self.ranges)
+    }
+}
+
+impl Display for Ranking {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.partial {
+            writeln!(f, "Partial, the search stopped before trying every formula")?;
+        }
+        writeln!(f, "Values:")?;
+        for v in &self.results.data {
+            write!(f, "\t{}", v)?;
+            if let Some(formula) = self.formula(v) {
+                writeln!(f, "        spreadsheet formula: {}", formula)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
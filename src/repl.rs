@@ -0,0 +1,329 @@
+//! Interactive shell: the file is loaded once, then searched again and again
+//! while the goal and the constraints change.
+
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+
+use cal_cu_lator::dataset::Dataset;
+use cal_cu_lator::output::{self, OutputFormat};
+use cal_cu_lator::ranking::Ranking;
+use cal_cu_lator::run::{self, RunOptions, STDIN};
+use cal_cu_lator::sorted_vec::SortedVec;
+use cal_cu_lator::{Error, Goal, Permutation, equivalence, verify};
+use clap::ValueEnum;
+
+use crate::{interrupt, jobfile};
+
+const VERIFY_TOLERANCE: f64 = 0.005;
+
+const HELP: &str = "\
+load FILE              read FILE, keeping the goal and the constraints
+fields                 list the fields, pinned and excluded ones marked
+goal VALUE...          set the goal, one value per period with --holdout
+rank N                 keep N results
+pin FIELD...           make every formula use FIELD
+exclude FIELD...       keep FIELD out of every formula
+unpin FIELD...         undo pin
+include FIELD...       undo exclude
+solve                  search with the current goal and constraints
+show [N]               print the first N results of the last search
+verify N | FORMULA     total result N or a formula like \"+ AAAAA - BBBBB\"
+export FILE [FORMAT]   write the last results as text, json, ndjson or csv
+help                   print this
+quit                   leave";
+
+pub struct Repl {
+    options: RunOptions,
+    file: Option<String>,
+    dataset: Option<Dataset>,
+    goal: Option<Goal>,
+    rank_size: usize,
+    ranking: Option<Ranking>,
+}
+
+impl Repl {
+    pub fn new(options: RunOptions, rank_size: usize) -> Self {
+        Repl {
+            options,
+            file: None,
+            dataset: None,
+            goal: None,
+            rank_size,
+            ranking: None,
+        }
+    }
+
+    /// Runs the commands read from `input` until it ends or `quit`, errors
+    /// are printed and the shell goes on.
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        input: R,
+        mut out: W,
+        prompt: bool,
+    ) -> Result<(), Error> {
+        let write_error = |e| Error::io("<stdout>", e);
+        let mut lines = input.lines();
+        loop {
+            if prompt {
+                write!(out, "> ")
+                    .and_then(|_| out.flush())
+                    .map_err(write_error)?;
+            }
+            let Some(line) = lines.next() else {
+                break;
+            };
+            let line = line.map_err(|e| Error::io("<stdin>", e))?;
+            let (command, args) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            let args = args.trim();
+            match command {
+                "" => {}
+                "quit" | "exit" => break,
+                "help" => writeln!(out, "{}", HELP).map_err(write_error)?,
+                _ => {
+                    if let Err(err) = self.execute(command, args, &mut out) {
+                        if let Error::Io { source, .. } = &err
+                            && source == "<stdout>"
+                        {
+                            return Err(err);
+                        }
+                        writeln!(out, "error: {}", err).map_err(write_error)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn execute<W: Write>(&mut self, command: &str, args: &str, out: &mut W) -> Result<(), Error> {
+        let write_error = |e| Error::io("<stdout>", e);
+        let names: Vec<String> = args
+            .split([',', ' '])
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        match command {
+            "load" => self.load(args, out)?,
+            "fields" => {
+                let dataset = self.dataset()?;
+                let constraints = &self.options.constraints;
+                for item in &dataset.items {
+                    let mark = if constraints.pinned.contains(&item.name) {
+                        " (pinned)"
+                    } else if constraints.excluded.contains(&item.name) {
+                        " (excluded)"
+                    } else {
+                        ""
+                    };
+                    writeln!(out, "{}{}", item.name, mark).map_err(write_error)?;
+                }
+            }
+            "goal" => {
+                let values: Vec<f64> = names
+                    .iter()
+                    .map(|v| v.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| Error::InvalidGoal(format!("invalid goal {}", args)))?;
+                let goal = match values.as_slice() {
+                    [] => return Err(Error::InvalidGoal("missing goal".to_string())),
+                    [total] => Goal::Total(*total),
+                    _ => Goal::PerPeriod(values),
+                };
+                jobfile::goals_for(&goal, self.options.holdout.is_some())?;
+                self.goal = Some(goal);
+            }
+            "rank" => {
+                self.rank_size = args
+                    .parse()
+                    .map_err(|_| Error::InvalidOption(format!("invalid rank size {}", args)))?;
+            }
+            "pin" | "exclude" | "unpin" | "include" => {
+                if names.is_empty() {
+                    return Err(Error::InvalidOption(format!("{} needs fields", command)));
+                }
+                let dataset = self.dataset()?;
+                if let Some(name) = names
+                    .iter()
+                    .find(|name| !dataset.items.iter().any(|i| &i.name == *name))
+                {
+                    return Err(Error::InvalidOption(format!("field {} not found", name)));
+                }
+                let constraints = &mut self.options.constraints;
+                let (list, other) = match command {
+                    "pin" | "unpin" => (&mut constraints.pinned, &mut constraints.excluded),
+                    _ => (&mut constraints.excluded, &mut constraints.pinned),
+                };
+                for name in names {
+                    list.retain(|n| *n != name);
+                    if command == "pin" || command == "exclude" {
+                        other.retain(|n| *n != name);
+                        list.push(name);
+                    }
+                }
+            }
+            "solve" => {
+                let goal = self
+                    .goal
+                    .as_ref()
+                    .ok_or_else(|| Error::InvalidGoal("set a goal first".to_string()))?;
+                let goals = jobfile::goals_for(goal, self.options.holdout.is_some())?;
+                let dataset = self.dataset()?.clone();
+                let file = self.file.as_deref().unwrap_or_default();
+                // a search stopped by Ctrl-C leaves the next ones alone
+                self.options.control = self.options.control.renewed();
+                let _watch = interrupt::watch(&self.options.control);
+                let ranking =
+                    run::solve_dataset(dataset, file, &goals, self.rank_size, &self.options)?;
+                let partial = if ranking.partial { ", partial" } else { "" };
+                writeln!(out, "{} results{}", ranking.results.data.len(), partial)
+                    .map_err(write_error)?;
+                self.ranking = Some(ranking);
+                self.show(self.rank_size.min(5), out)?;
+            }
+            "show" => {
+                let count = match args {
+                    "" => self.rank_size,
+                    count => count
+                        .parse()
+                        .map_err(|_| Error::InvalidOption(format!("invalid count {}", count)))?,
+                };
+                self.show(count, out)?;
+            }
+            "verify" => {
+                let (formula, fields) = match args.parse::<usize>() {
+                    Ok(rank) => {
+                        let result = rank
+                            .checked_sub(1)
+                            .and_then(|i| self.ranking()?.results.data.get(i))
+                            .ok_or_else(|| Error::InvalidOption(format!("no result {}", rank)))?;
+                        // collapsed fields are verified through the field
+                        // whose values they were searched with
+                        (
+                            pretty_formula(result, |name| name),
+                            pretty_formula(result, equivalence::representative),
+                        )
+                    }
+                    Err(_) => (args.to_string(), args.to_string()),
+                };
+                let goal = self
+                    .goal
+                    .as_ref()
+                    .ok_or_else(|| Error::InvalidGoal("set a goal first".to_string()))?;
+                let verification = verify::verify_formula(
+                    &fields,
+                    &self.dataset()?.items,
+                    goal.total(),
+                    VERIFY_TOLERANCE,
+                )?;
+                writeln!(
+                    out,
+                    "{}\ntotal: {}\ngoal: {}\ndifference: {}\n{}",
+                    formula,
+                    verification.total,
+                    verification.goal,
+                    verification.difference,
+                    if verification.matches {
+                        "match"
+                    } else {
+                        "no match"
+                    }
+                )
+                .map_err(write_error)?;
+            }
+            "export" => {
+                let (path, format) = args.split_once(' ').unwrap_or((args, ""));
+                if path.is_empty() {
+                    return Err(Error::InvalidOption("export needs a file".to_string()));
+                }
+                let format = match format.trim() {
+                    "" => OutputFormat::Text,
+                    format => OutputFormat::from_str(format, true)
+                        .map_err(|_| Error::InvalidOption(format!("unknown format {}", format)))?,
+                };
+                let ranking = self
+                    .ranking()
+                    .ok_or_else(|| Error::InvalidOption("nothing to export yet".to_string()))?;
+                let file = File::create(path).map_err(|e| Error::io(path, e))?;
+                let mut writer = BufWriter::new(file);
+                output::write_report(
+                    &mut writer,
+                    format,
+                    std::slice::from_ref(ranking),
+                    &SortedVec::new(0),
+                )
+                .and_then(|_| writer.flush())
+                .map_err(|e| Error::io(path, e))?;
+            }
+            _ => {
+                return Err(Error::InvalidOption(format!(
+                    "unknown command {}, try help",
+                    command
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load<W: Write>(&mut self, path: &str, out: &mut W) -> Result<(), Error> {
+        // stdin is where the commands come from
+        if path.is_empty() || path == STDIN {
+            return Err(Error::InvalidOption("load needs a file".to_string()));
+        }
+        let dataset = run::load_dataset(path, &self.options.loader)?;
+        writeln!(
+            out,
+            "{} fields over {} periods",
+            dataset.items.len(),
+            dataset.periods.len()
+        )
+        .map_err(|e| Error::io("<stdout>", e))?;
+        self.file = Some(path.to_string());
+        self.dataset = Some(dataset);
+        self.ranking = None;
+        Ok(())
+    }
+
+    fn show<W: Write>(&self, count: usize, out: &mut W) -> Result<(), Error> {
+        let ranking = self
+            .ranking()
+            .ok_or_else(|| Error::InvalidOption("nothing to show yet, solve first".to_string()))?;
+        for (i, result) in ranking.results.data.iter().take(count).enumerate() {
+            write!(
+                out,
+                "{:>3}. error {}  {}",
+                i + 1,
+                result.get_error(),
+                pretty_formula(result, |name| name)
+            )
+            .map_err(|e| Error::io("<stdout>", e))?;
+            if let Some(validation_error) = result.get_validation_error() {
+                write!(out, "  (validation error {})", validation_error)
+                    .map_err(|e| Error::io("<stdout>", e))?;
+            }
+            writeln!(out).map_err(|e| Error::io("<stdout>", e))?;
+        }
+        Ok(())
+    }
+
+    fn dataset(&self) -> Result<&Dataset, Error> {
+        self.dataset
+            .as_ref()
+            .ok_or_else(|| Error::InvalidOption("load a file first".to_string()))
+    }
+
+    fn ranking(&self) -> Option<&Ranking> {
+        self.ranking.as_ref()
+    }
+}
+
+/// The fields of `result` with their signs, as `verify` reads them, each
+/// field written as `name` gives it.
+fn pretty_formula<P: Permutation>(result: &P, name: impl Fn(&str) -> &str) -> String {
+    result
+        .get_field_names()
+        .iter()
+        .zip(result.get_signs())
+        .filter(|(_, sign)| *sign != 0)
+        .map(|(field, sign)| format!("{} {}", if sign > 0 { '+' } else { '-' }, name(field)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,225 @@
+use std::fs::File;
+
+use crate::constraints::Constraints;
+use crate::control::SearchControl;
+use crate::dataset::Dataset;
+use crate::equivalence::{self, Equivalence};
+use crate::error::Error;
+use crate::formula::SheetRange;
+use crate::loader::{self, LoaderOptions, MissingValues};
+use crate::pruning;
+use crate::ranking::Ranking;
+use crate::solver::{Goal, Solver};
+
+/// Name standing for stdin in place of a file.
+pub const STDIN: &str = "-";
+
+/// One search: a file, the goals to reach and how many results to keep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    pub file: String,
+    pub goals: Vec<f64>,
+    pub rank_size: usize,
+}
+
+/// Options shared by every file of a run.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    pub loader: LoaderOptions,
+    /// Periods held out for cross-validation, by name or 1-based position.
+    /// Goals are per period when set.
+    pub holdout: Option<Vec<String>>,
+    /// Collapse interchangeable fields within this tolerance before searching.
+    pub equivalence_tolerance: Option<f64>,
+    /// Search all-zero fields too instead of pruning them.
+    pub keep_zero_rows: bool,
+    /// Prune fields that are a `+1`/`-1` sum of other fields, multiples of
+    /// fields are not detected.
+    pub prune_redundant: bool,
+    pub constraints: Constraints,
+    /// Follows or stops the searches.
+    pub control: SearchControl,
+}
+
+pub fn load_dataset(filename: &str, options: &LoaderOptions) -> Result<Dataset, Error> {
+    if filename == STDIN {
+        return loader::read_items(std::io::stdin().lock(), "<stdin>", options);
+    }
+    let file_reader = File::open(filename).map_err(|e| Error::io(filename, e))?;
+
+    loader::read_items(file_reader, filename, options)
+}
+
+/// Loads a file, prunes and collapses its fields as `options` say and ranks
+/// the formulas closest to `goals`.
+pub fn run_cu_solver(
+    filename: &str,
+    goals: &[f64],
+    rank_size: usize,
+    options: &RunOptions,
+) -> Result<Ranking, Error> {
+    let dataset = load_dataset(filename, &options.loader)?;
+    solve_dataset(dataset, filename, goals, rank_size, options)
+}
+
+/// Like [`run_cu_solver`] on a dataset that is already loaded, `filename`
+/// only names it in messages and in the ranking.
+pub fn solve_dataset(
+    dataset: Dataset,
+    filename: &str,
+    goals: &[f64],
+    rank_size: usize,
+    options: &RunOptions,
+) -> Result<Ranking, Error> {
+    // only per-period matching has periods to leave out
+    if options.holdout.is_none()
+        && options.loader.missing_values == MissingValues::Unknown
+        && !dataset.missing.is_empty()
+    {
+        return Err(Error::InvalidOption(format!(
+            "{}: unknown values are only left out when periods are held out, read blank cells as zero instead",
+            filename
+        )));
+    }
+    if !dataset.missing.is_empty() {
+        let action = match options.loader.missing_values {
+            MissingValues::Error => "",
+            MissingValues::Zero => "read as zero",
+            MissingValues::Unknown => "left unknown",
+            MissingValues::Reject => "rejected their fields",
+        };
+        let mut notice = format!(
+            "{} blank values in {} {}:",
+            dataset.missing.len(),
+            filename,
+            action
+        );
+        for missing in &dataset.missing {
+            notice.push_str(&format!("\n\t{}", missing));
+        }
+        options.control.notice(notice);
+    }
+    let holdout: Option<Vec<usize>> = match &options.holdout {
+        Some(periods) => Some(
+            periods
+                .iter()
+                .map(|p| {
+                    dataset.period_index(p).ok_or_else(|| {
+                        Error::InvalidOption(format!("{}: unknown period {}", filename, p))
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        None => None,
+    };
+    let unknown = dataset.unknown_periods();
+    if let Some(holdout) = &holdout {
+        let training: Vec<usize> = (0..dataset.periods.len())
+            .filter(|p| !holdout.contains(p) && !unknown.contains(p))
+            .collect();
+        let holdout: Vec<usize> = holdout
+            .iter()
+            .copied()
+            .filter(|p| !unknown.contains(p))
+            .collect();
+        options.control.notice(format!(
+            "Training {} on {}, validating on {}",
+            filename,
+            dataset.period_names(&training),
+            dataset.period_names(&holdout)
+        ));
+        if !unknown.is_empty() {
+            options.control.notice(format!(
+                "Leaving out periods with unknown values: {}",
+                dataset.period_names(&unknown)
+            ));
+        }
+    }
+
+    let sheet_ranges: Vec<(String, SheetRange)> = dataset
+        .items
+        .iter()
+        .filter_map(|i| Some((i.name.clone(), dataset.range_of(&i.name)?.clone())))
+        .collect();
+
+    // excluded fields go before pruning, so nothing is expressed through them
+    let items = options
+        .constraints
+        .exclude(dataset.items)
+        .map_err(|e| e.in_file(filename))?;
+
+    let (mut items, pruned) =
+        pruning::prune(items, !options.keep_zero_rows, options.prune_redundant);
+    if !pruned.is_empty() {
+        let mut notice = format!("Pruned {} fields in {}:", pruned.len(), filename);
+        for p in &pruned {
+            notice.push_str(&format!("\n\t{}", p));
+        }
+        options.control.notice(notice);
+    }
+
+    if let Some(tolerance) = options.equivalence_tolerance {
+        // per-month matching needs the whole vector to be equal
+        let equivalence = if options.holdout.is_some() {
+            Equivalence::Values
+        } else {
+            Equivalence::Total
+        };
+        let num_fields = items.len();
+        items = equivalence::collapse_equivalent(items, tolerance, equivalence);
+        if items.len() < num_fields {
+            let mut notice = format!(
+                "Collapsed {} equivalent fields in {}:",
+                num_fields - items.len(),
+                filename
+            );
+            for item in items
+                .iter()
+                .filter(|i| i.name.contains(equivalence::SEPARATOR))
+            {
+                notice.push_str(&format!("\n\t{}", item.name));
+            }
+            options.control.notice(notice);
+        }
+    }
+
+    // collapsed fields use the values, and so the cells, of their first member
+    let ranges = items
+        .iter()
+        .map(|i| {
+            let name = equivalence::representative(&i.name);
+            sheet_ranges
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, range)| range.clone())
+        })
+        .collect();
+
+    let solver = Solver::new(items)
+        .rank_size(rank_size)
+        .control(options.control.clone())
+        .constraints(Constraints {
+            pinned: options.constraints.pinned.clone(),
+            excluded: Vec::new(),
+        });
+    let solver = match holdout {
+        Some(holdout) => solver.goal(goals.to_vec()).holdout(holdout),
+        None => solver.goal(Goal::Total(goals[0])),
+    };
+    let solution = solver.solve().map_err(|e| e.in_file(filename))?;
+    if solution.partial {
+        options.control.notice(format!(
+            "Search of {} stopped early, results are the best found so far",
+            filename
+        ));
+    }
+
+    Ok(Ranking {
+        file: filename.to_string(),
+        field_names: solution.field_names,
+        goal: solution.goal,
+        results: solution.results,
+        ranges,
+        partial: solution.partial,
+    })
+}
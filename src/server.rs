@@ -0,0 +1,409 @@
+//! Server mode: newline-delimited JSON requests on stdin, one JSON object
+//! per line on stdout for each response and progress event.
+//!
+//! Every request has a `command` and an `id` of any JSON type, which is
+//! repeated in everything written back about it:
+//!
+//! ```text
+//! {"id": 1, "command": "load", "name": "q1", "path": "test_data.csv"}
+//! {"id": 1, "type": "loaded", "dataset": "q1", "periods": [...], ...}
+//! {"id": 2, "command": "solve", "dataset": "q1", "goal": 58200.23}
+//! {"id": 2, "type": "progress", "percent": 10}
+//! {"id": 3, "command": "cancel", "target": 2}
+//! {"id": 3, "type": "ok"}
+//! {"id": 2, "type": "error", "kind": "cancelled", "message": "cancelled"}
+//! ```
+//!
+//! Loads and verifications are answered in order, searches run in the
+//! background so they can be cancelled. The server stops once stdin is
+//! closed and the running searches are done.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use cal_cu_lator::constraints::Constraints;
+use cal_cu_lator::dataset::Dataset;
+use cal_cu_lator::output::{self, Report};
+use cal_cu_lator::ranking::Ranking;
+use cal_cu_lator::run::{self, RunOptions};
+use cal_cu_lator::solver::DEFAULT_RANK_SIZE;
+use cal_cu_lator::{Error, Goal, SearchControl, combinedresult, loader, verify};
+
+use crate::jobfile::{self, LoaderSettings, SolverSettings};
+
+const DEFAULT_TOLERANCE: f64 = 0.005;
+
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    id: Value,
+    #[serde(flatten)]
+    request: Request,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+enum Request {
+    /// Reads a dataset from `path` or from `content` and keeps it as `name`,
+    /// the path when not given.
+    Load {
+        name: Option<String>,
+        path: Option<String>,
+        content: Option<String>,
+        #[serde(default)]
+        loader: LoaderSettings,
+    },
+    Solve(SolveRequest),
+    Verify {
+        dataset: String,
+        goal: f64,
+        formula: String,
+        #[serde(default = "default_tolerance")]
+        tolerance: f64,
+    },
+    /// Runs every search and merges their rankings.
+    Combine {
+        searches: Vec<Search>,
+        #[serde(default = "default_rank_size")]
+        rank_size: usize,
+        #[serde(default)]
+        solver: SolverSettings,
+        #[serde(default)]
+        constraints: Constraints,
+    },
+    /// Stops the search started by the request with id `target`.
+    Cancel {
+        target: Value,
+    },
+}
+
+/// A search on a loaded dataset.
+#[derive(Debug, Deserialize)]
+pub struct Search {
+    pub dataset: String,
+    pub goal: Goal,
+    #[serde(default = "default_rank_size")]
+    pub rank_size: usize,
+}
+
+/// A search with the options to run it with.
+#[derive(Debug, Deserialize)]
+pub struct SolveRequest {
+    #[serde(flatten)]
+    pub search: Search,
+    #[serde(default)]
+    pub solver: SolverSettings,
+    #[serde(default)]
+    pub constraints: Constraints,
+}
+
+/// What a thread needs to run a [`Search`], with its own copy of the dataset.
+pub struct SearchJob {
+    dataset: Dataset,
+    name: String,
+    goals: Vec<f64>,
+    rank_size: usize,
+}
+
+impl SearchJob {
+    pub fn new(
+        datasets: &HashMap<String, Dataset>,
+        search: Search,
+        options: &RunOptions,
+    ) -> Result<Self, Error> {
+        let dataset = datasets
+            .get(&search.dataset)
+            .ok_or_else(|| Error::InvalidOption(format!("unknown dataset {}", search.dataset)))?
+            .clone();
+        let goals = jobfile::goals_for(&search.goal, options.holdout.is_some())
+            .map_err(|e| e.in_file(&search.dataset))?;
+        Ok(SearchJob {
+            dataset,
+            name: search.dataset,
+            goals,
+            rank_size: search.rank_size,
+        })
+    }
+
+    pub fn run(self, options: &RunOptions) -> Result<Ranking, Error> {
+        run::solve_dataset(
+            self.dataset,
+            &self.name,
+            &self.goals,
+            self.rank_size,
+            options,
+        )
+    }
+}
+
+/// What is reported about a dataset once it is loaded.
+pub fn dataset_summary(name: &str, dataset: &Dataset) -> Value {
+    let fields: Vec<&str> = dataset.items.iter().map(|i| i.name.as_str()).collect();
+    json!({
+        "dataset": name,
+        "periods": dataset.periods,
+        "fields": fields,
+        "missing": dataset.missing.len(),
+        "rejected": dataset.rejected,
+    })
+}
+
+fn default_rank_size() -> usize {
+    DEFAULT_RANK_SIZE
+}
+
+fn default_tolerance() -> f64 {
+    DEFAULT_TOLERANCE
+}
+
+#[derive(Serialize)]
+struct Response<'a, T> {
+    id: &'a Value,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(flatten)]
+    body: T,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub kind: &'static str,
+    pub message: String,
+}
+
+impl From<&Error> for ErrorBody {
+    fn from(err: &Error) -> Self {
+        ErrorBody {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Writes whole lines, shared by the threads running searches.
+#[derive(Clone)]
+struct Responder {
+    out: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl Responder {
+    fn send<T: Serialize>(&self, id: &Value, kind: &'static str, body: T) {
+        let line = serde_json::to_string(&Response { id, kind, body })
+            .expect("responses serialize to JSON");
+        let mut out = self.out.lock().unwrap();
+        // a closed stdout leaves nobody to answer, the searches just finish
+        let _ = writeln!(out, "{}", line).and_then(|_| out.flush());
+    }
+
+    fn error(&self, id: &Value, err: &Error) {
+        self.send(id, "error", ErrorBody::from(err));
+    }
+}
+
+struct Server {
+    responder: Responder,
+    datasets: HashMap<String, Dataset>,
+    /// Searches still running, by the id of their request.
+    running: Arc<Mutex<HashMap<String, SearchControl>>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+/// Answers the requests read from `input` on `output` until `input` ends.
+pub fn serve<R: BufRead>(input: R, output: impl Write + Send + 'static) -> Result<(), Error> {
+    let mut server = Server {
+        responder: Responder {
+            out: Arc::new(Mutex::new(Box::new(output))),
+        },
+        datasets: HashMap::new(),
+        running: Arc::new(Mutex::new(HashMap::new())),
+        threads: Vec::new(),
+    };
+
+    for (number, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| Error::io("<stdin>", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Envelope>(&line) {
+            Ok(Envelope { id, request }) => {
+                if let Err(err) = server.handle(&id, request) {
+                    server.responder.error(&id, &err);
+                }
+            }
+            Err(e) => {
+                // the id is still worth echoing when only the command is wrong
+                let id = serde_json::from_str::<Value>(&line)
+                    .ok()
+                    .and_then(|v| v.get("id").cloned())
+                    .unwrap_or_default();
+                // each request is a line of the input
+                let mut err = Error::from_json("request", &e);
+                if let Error::Parse { line, .. } = &mut err {
+                    *line = Some(number as u64 + 1);
+                }
+                server.responder.error(&id, &err);
+            }
+        }
+        server.threads.retain(|t| !t.is_finished());
+    }
+
+    for thread in server.threads {
+        if let Err(panic) = thread.join() {
+            std::panic::resume_unwind(panic);
+        }
+    }
+    Ok(())
+}
+
+impl Server {
+    fn handle(&mut self, id: &Value, request: Request) -> Result<(), Error> {
+        match request {
+            Request::Load {
+                name,
+                path,
+                content,
+                loader,
+            } => {
+                let options = loader.options()?;
+                let (name, dataset) = match (path, content) {
+                    (Some(path), None) => {
+                        // stdin is where the requests come from
+                        if path == run::STDIN {
+                            return Err(Error::InvalidOption(
+                                "load can't read stdin, send the content instead".to_string(),
+                            ));
+                        }
+                        let dataset = run::load_dataset(&path, &options)?;
+                        (name.unwrap_or(path), dataset)
+                    }
+                    (None, Some(content)) => {
+                        let name = name.ok_or_else(|| {
+                            Error::InvalidOption("content needs a name".to_string())
+                        })?;
+                        let dataset = loader::read_items(content.as_bytes(), &name, &options)?;
+                        (name, dataset)
+                    }
+                    _ => {
+                        return Err(Error::InvalidOption(
+                            "load needs either a path or a content".to_string(),
+                        ));
+                    }
+                };
+                self.responder
+                    .send(id, "loaded", dataset_summary(&name, &dataset));
+                self.datasets.insert(name, dataset);
+            }
+            Request::Solve(SolveRequest {
+                search,
+                solver,
+                constraints,
+            }) => {
+                let options = solver.run_options(Default::default(), constraints)?;
+                let job = SearchJob::new(&self.datasets, search, &options)?;
+                self.spawn(id, options, move |options| {
+                    let ranking = job.run(options)?;
+                    Ok((
+                        "ranking",
+                        serde_json::to_value(output::ranking_record(&ranking)),
+                    ))
+                })?;
+            }
+            Request::Verify {
+                dataset,
+                goal,
+                formula,
+                tolerance,
+            } => {
+                let dataset = self
+                    .datasets
+                    .get(&dataset)
+                    .ok_or_else(|| Error::InvalidOption(format!("unknown dataset {}", dataset)))?;
+                let verification =
+                    verify::verify_formula(&formula, &dataset.items, goal, tolerance)?;
+                self.responder.send(id, "verification", verification);
+            }
+            Request::Combine {
+                searches,
+                rank_size,
+                solver,
+                constraints,
+            } => {
+                if searches.len() < 2 {
+                    return Err(Error::InvalidOption(
+                        "combine needs at least two searches".to_string(),
+                    ));
+                }
+                let options = solver.run_options(Default::default(), constraints)?;
+                let jobs = searches
+                    .into_iter()
+                    .map(|search| SearchJob::new(&self.datasets, search, &options))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.spawn(id, options, move |options| {
+                    let rankings = jobs
+                        .into_iter()
+                        .map(|job| job.run(options))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let combined = combinedresult::combine(&rankings, rank_size);
+                    let report = Report {
+                        rankings: rankings.iter().map(output::ranking_record).collect(),
+                        combined: output::combined_records(&combined),
+                    };
+                    Ok(("combined", serde_json::to_value(report)))
+                })?;
+            }
+            Request::Cancel { target } => {
+                let running = self.running.lock().unwrap();
+                let control = running.get(&target.to_string()).ok_or_else(|| {
+                    Error::InvalidOption(format!("no running request with id {}", target))
+                })?;
+                control.cancel();
+                self.responder.send(id, "ok", json!({}));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `work` in its own thread, reporting its progress and its result
+    /// under `id`, where it can be cancelled until it is done.
+    fn spawn<F>(&mut self, id: &Value, mut options: RunOptions, work: F) -> Result<(), Error>
+    where
+        F: FnOnce(&RunOptions) -> Result<(&'static str, serde_json::Result<Value>), Error>
+            + Send
+            + 'static,
+    {
+        let key = id.to_string();
+        let progress = self.responder.clone();
+        let progress_id = id.clone();
+        options.control = options.control.on_progress(move |percent| {
+            progress.send(&progress_id, "progress", json!({ "percent": percent }));
+        });
+        {
+            let mut running = self.running.lock().unwrap();
+            if running.contains_key(&key) {
+                return Err(Error::InvalidOption(format!(
+                    "a request with id {} is still running",
+                    id
+                )));
+            }
+            running.insert(key.clone(), options.control.clone());
+        }
+
+        let responder = self.responder.clone();
+        let running = Arc::clone(&self.running);
+        let id = id.clone();
+        self.threads.push(thread::spawn(move || {
+            match work(&options) {
+                Ok((kind, Ok(body))) => responder.send(&id, kind, body),
+                Ok((_, Err(e))) => responder.error(&id, &Error::io("<stdout>", e.into())),
+                Err(err) => responder.error(&id, &err),
+            }
+            running.lock().unwrap().remove(&key);
+        }));
+        Ok(())
+    }
+}
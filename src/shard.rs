@@ -0,0 +1,145 @@
+//! Splits a search across processes: each one searches a slice of the
+//! selections and writes its partial rankings, which are then merged into
+//! the rankings of the whole search.
+
+use std::fmt::Display;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::ranking::Ranking;
+use crate::sorted_vec::SortedVec;
+
+/// Slice `index` (1-based) out of `count` slices of a search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    pub index: u32,
+    pub count: u32,
+}
+
+impl FromStr for Shard {
+    type Err = Error;
+
+    /// Parses `i/N`.
+    fn from_str(shard: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidOption(format!("invalid shard {}, expected i/N", shard));
+        let (index, count) = shard.split_once('/').ok_or_else(invalid)?;
+        let index: u32 = index.trim().parse().map_err(|_| invalid())?;
+        let count: u32 = count.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(Error::InvalidOption(format!(
+                "shard {} is out of range (1-{})",
+                index, count
+            )));
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// The partial rankings of one shard, one per search of the run.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShardFile {
+    pub shard: Shard,
+    pub rankings: Vec<Ranking>,
+}
+
+/// Writes the partial rankings of `shard` as JSON.
+pub fn write<W: Write>(mut writer: W, shard: Shard, rankings: Vec<Ranking>) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(&ShardFile { shard, rankings })?;
+    writeln!(writer, "{}", json)
+}
+
+/// Reads partial rankings written by [`write`], `source` names them in
+/// errors.
+pub fn read<R: Read>(reader: R, source: &str) -> Result<ShardFile, Error> {
+    let mut file: ShardFile =
+        serde_json::from_reader(reader).map_err(|e| Error::from_json(source, &e))?;
+    // the field names are written once per ranking, not once per result
+    for ranking in file.rankings.iter_mut() {
+        for result in ranking.results.data.iter_mut() {
+            result.field_names = ranking.field_names.clone();
+        }
+    }
+    Ok(file)
+}
+
+/// The shards of the run of `files` that none of them holds.
+pub fn missing(files: &[ShardFile]) -> Vec<Shard> {
+    let Some(first) = files.first() else {
+        return Vec::new();
+    };
+    let count = first.shard.count;
+    (1..=count)
+        .filter(|&index| !files.iter().any(|file| file.shard.index == index))
+        .map(|index| Shard { index, count })
+        .collect()
+}
+
+/// Merges the partial rankings of the shards of a run into its rankings.
+///
+/// Every shard must come from the same run and appear once. Rankings are
+/// partial when a shard stopped early or is [`missing`].
+pub fn merge(files: Vec<ShardFile>) -> Result<Vec<Ranking>, Error> {
+    let complete = missing(&files).is_empty();
+    let mismatch = |what: &str| {
+        Error::InvalidOption(format!("the shards are from different searches: {}", what))
+    };
+    let mut files = files.into_iter();
+    let first = files
+        .next()
+        .ok_or_else(|| Error::EmptyInput("no shards to merge".to_string()))?;
+    let count = first.shard.count;
+    let mut seen = vec![first.shard.index];
+    let mut merged = first.rankings;
+
+    for file in files {
+        if file.shard.count != count {
+            return Err(mismatch("shard counts differ"));
+        }
+        if seen.contains(&file.shard.index) {
+            return Err(Error::InvalidOption(format!(
+                "shard {} is given twice",
+                file.shard
+            )));
+        }
+        seen.push(file.shard.index);
+        if file.rankings.len() != merged.len() {
+            return Err(mismatch("numbers of rankings differ"));
+        }
+        merged = merged
+            .into_iter()
+            .zip(file.rankings)
+            .map(|(left, right)| {
+                if left.file != right.file
+                    || left.goal != right.goal
+                    || left.field_names != right.field_names
+                {
+                    return Err(mismatch(&format!(
+                        "{} and {} differ",
+                        left.file, right.file
+                    )));
+                }
+                Ok(Ranking {
+                    results: SortedVec::merged(left.results, right.results),
+                    partial: left.partial || right.partial,
+                    ..left
+                })
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    if !complete {
+        for ranking in merged.iter_mut() {
+            ranking.partial = true;
+        }
+    }
+    Ok(merged)
+}
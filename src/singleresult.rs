@@ -1,15 +1,23 @@
 use crate::permutation::{Permutation, PermutationKey};
+use crate::sorted_vec::SortedVec;
 use std::cmp::Ordering;
 use std::fmt::Display;
 
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+/// Serializes what the search found, without the field names every result
+/// of a search shares.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SingleResult {
+    #[serde(skip)]
     pub field_names: Vec<String>,
     pub permutation_sign: u32,
     pub permutation_select: u32,
     pub mask: u32,
     pub diff: f64,
     error: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    validation_error: Option<f64>,
 }
 
 impl Permutation for SingleResult {
@@ -36,6 +44,10 @@ impl Permutation for SingleResult {
     fn get_diff(&self) -> f64 {
         self.diff
     }
+
+    fn get_validation_error(&self) -> Option<f64> {
+        self.validation_error
+    }
 }
 
 impl Display for SingleResult {
@@ -62,12 +74,17 @@ impl SingleResult {
             mask,
             diff,
             error: err,
+            validation_error: None,
         }
     }
 
     pub fn get_own_key(&self) -> PermutationKey {
         self.get_key()
     }
+
+    pub fn set_validation_error(&mut self, err: f64) {
+        self.validation_error = Some(err);
+    }
 }
 
 impl PartialEq for SingleResult {
@@ -113,6 +130,17 @@ impl Default for SingleResult {
             mask: Default::default(),
             diff: f64::MAX,
             error: f64::MAX,
+            validation_error: None,
+        }
+    }
+}
+
+impl Display for SortedVec<SingleResult> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Values:")?;
+        for v in &self.data {
+            writeln!(f, "\t{}", v)?;
         }
+        Ok(())
     }
 }
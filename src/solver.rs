@@ -0,0 +1,615 @@
+use std::ops::Range;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::Deserialize;
+
+use crate::checkpoint;
+use crate::constraints::Constraints;
+use crate::control::{SearchControl, SearchEvent};
+use crate::crossvalidation;
+use crate::error::Error;
+use crate::item::Item;
+use crate::masked_permutation::MaskedPermutation;
+use crate::permutation::Permutation;
+use crate::progress::Progress;
+use crate::shard::Shard;
+use crate::singleresult::SingleResult;
+use crate::sorted_vec::SortedVec;
+
+/// Formulas kept in a ranking unless told otherwise.
+pub const DEFAULT_RANK_SIZE: usize = 10;
+// one bit per field in the select and sign masks, the top one is left free
+const MAX_FIELDS: usize = 31;
+// signs of a selection evaluated between two looks at the budget, so that
+// selections of many fields can be stopped halfway
+const SIGN_BATCH: u64 = 1 << 12;
+
+/// A total, or one total per period.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Goal {
+    Total(f64),
+    PerPeriod(Vec<f64>),
+}
+
+impl Goal {
+    /// The total over every period.
+    pub fn total(&self) -> f64 {
+        match self {
+            Goal::Total(total) => *total,
+            Goal::PerPeriod(goals) => goals.iter().sum(),
+        }
+    }
+}
+
+impl From<f64> for Goal {
+    fn from(total: f64) -> Self {
+        Goal::Total(total)
+    }
+}
+
+impl From<Vec<f64>> for Goal {
+    fn from(goals: Vec<f64>) -> Self {
+        Goal::PerPeriod(goals)
+    }
+}
+
+/// How far a formula is from the goal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Metric {
+    /// Difference between the total over every period and the total goal.
+    #[default]
+    Total,
+    /// Sum of the differences between each period and its own goal, needs
+    /// per-period goals.
+    PerPeriod,
+}
+
+/// The best formulas found by a [`Solver`].
+#[derive(Debug)]
+pub struct Solution {
+    /// Names of the fields searched, after the exclusions.
+    pub field_names: Vec<String>,
+    /// The total the errors are measured against, for cross-validation the
+    /// goal of the training periods.
+    pub goal: f64,
+    pub results: SortedVec<SingleResult>,
+    /// The search stopped before trying every formula, through
+    /// [`SearchControl::stop`] or its budget, so better ones may exist.
+    pub partial: bool,
+}
+
+/// Searches the signed sums of fields closest to a goal.
+///
+/// ```
+/// use cal_cu_lator::{Item, Solver};
+///
+/// let items = vec![
+///     Item { name: "BASE".to_string(), values: vec![1000.0, 1000.0] },
+///     Item { name: "TAX".to_string(), values: vec![200.0, 250.0] },
+/// ];
+/// let solution = Solver::new(items).goal(1550.0).rank_size(3).solve().unwrap();
+/// assert_eq!(solution.results.data[0].diff, 0.0);
+/// ```
+#[derive(Debug)]
+pub struct Solver {
+    fields: Vec<Item>,
+    goal: Goal,
+    rank_size: usize,
+    constraints: Constraints,
+    metric: Metric,
+    holdout: Vec<usize>,
+    control: SearchControl,
+}
+
+impl Solver {
+    pub fn new(fields: Vec<Item>) -> Self {
+        Solver {
+            fields,
+            goal: Goal::Total(0_f64),
+            rank_size: DEFAULT_RANK_SIZE,
+            constraints: Constraints::default(),
+            metric: Metric::default(),
+            holdout: Vec::new(),
+            control: SearchControl::default(),
+        }
+    }
+
+    pub fn goal(mut self, goal: impl Into<Goal>) -> Self {
+        self.goal = goal.into();
+        self
+    }
+
+    pub fn rank_size(mut self, rank_size: usize) -> Self {
+        self.rank_size = rank_size;
+        self
+    }
+
+    pub fn constraints(mut self, constraints: Constraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Periods (0-based) held out to validate formulas fitted on the other
+    /// ones, needs per-period goals.
+    pub fn holdout(mut self, periods: Vec<usize>) -> Self {
+        self.holdout = periods;
+        self
+    }
+
+    /// Follows or stops the search through `control`.
+    pub fn control(mut self, control: SearchControl) -> Self {
+        self.control = control;
+        self
+    }
+
+    pub fn solve(self) -> Result<Solution, Error> {
+        let control = self.control.start();
+        let fields = self.constraints.exclude(self.fields)?;
+        if fields.is_empty() {
+            return Err(Error::EmptyInput("no fields to search".to_string()));
+        }
+        let goal_values = match &self.goal {
+            Goal::Total(total) => std::slice::from_ref(total),
+            Goal::PerPeriod(goals) => goals.as_slice(),
+        };
+        if let Some(goal) = goal_values.iter().find(|g| !g.is_finite()) {
+            return Err(Error::InvalidGoal(format!("goal {} is not a number", goal)));
+        }
+        let pinned_mask = self.constraints.pinned_mask(&fields)?;
+        let field_names = fields.iter().map(|i| i.name.clone()).collect();
+
+        let per_period_goals = match &self.goal {
+            Goal::PerPeriod(goals) => {
+                if let Some(field) = fields.iter().find(|f| f.values.len() != goals.len()) {
+                    return Err(Error::InvalidGoal(format!(
+                        "field {} has {} periods but {} goals were given",
+                        field.name,
+                        field.values.len(),
+                        goals.len()
+                    )));
+                }
+                Some(goals)
+            }
+            Goal::Total(_) => None,
+        };
+
+        if !self.holdout.is_empty() {
+            let goals = per_period_goals.ok_or_else(|| {
+                Error::InvalidGoal("a holdout needs per-period goals".to_string())
+            })?;
+            if self.metric != Metric::Total {
+                return Err(Error::InvalidOption(
+                    "cross-validation only ranks by total".to_string(),
+                ));
+            }
+            let results = crossvalidation::cross_validate(
+                &fields,
+                goals,
+                &self.holdout,
+                self.rank_size,
+                pinned_mask,
+                &control,
+            )?;
+            // periods with unknown values are left out of the training goal
+            let goal = (0..goals.len())
+                .filter(|p| !self.holdout.contains(p))
+                .filter(|&p| fields.iter().all(|f| !f.values[p].is_nan()))
+                .map(|p| goals[p])
+                .sum();
+            return Ok(Solution {
+                field_names,
+                goal,
+                results,
+                partial: control.stopped_early(),
+            });
+        }
+
+        let results = match self.metric {
+            Metric::Total => search_total(
+                &fields,
+                self.goal.total(),
+                self.rank_size,
+                pinned_mask,
+                &control,
+            )?,
+            Metric::PerPeriod => {
+                let goals = per_period_goals.ok_or_else(|| {
+                    Error::InvalidGoal("the per-period metric needs per-period goals".to_string())
+                })?;
+                search(
+                    &fields,
+                    self.rank_size,
+                    pinned_mask,
+                    &control,
+                    ("per-period", goals),
+                    |sign, select| get_period_errors(sign, select, &fields, goals),
+                )?
+            }
+        };
+
+        Ok(Solution {
+            field_names,
+            goal: self.goal.total(),
+            results,
+            partial: control.stopped_early(),
+        })
+    }
+}
+
+pub fn get_total_for_perm(permutation_sign: u32, permutation_select: u32, fields: &[Item]) -> f64 {
+    let mut total = 0_f64;
+    // for each field in the pay slip...
+    for (pay_field_n, pay_field) in fields.iter().enumerate() {
+        // determine if it should be selected
+        let select_field = ((permutation_select >> pay_field_n) & 1) != 0;
+        if !select_field {
+            continue;
+        }
+
+        // determine the sign
+        let make_field_negative = ((permutation_sign >> pay_field_n) & 1) == 0;
+        for month_amount in &pay_field.values {
+            let month_amount_with_sign = if make_field_negative {
+                month_amount * -1_f64
+            } else {
+                *month_amount
+            };
+            total += month_amount_with_sign;
+        }
+    }
+    total
+}
+
+/// Adds up the signed errors of every period and their absolute values.
+fn get_period_errors(
+    permutation_sign: u32,
+    permutation_select: u32,
+    fields: &[Item],
+    goals: &[f64],
+) -> (f64, f64) {
+    goals
+        .iter()
+        .enumerate()
+        .map(|(period, goal)| {
+            let period_total: f64 = fields
+                .iter()
+                .enumerate()
+                .filter(|(n, _)| (permutation_select >> n) & 1 != 0)
+                .map(|(n, field)| {
+                    if (permutation_sign >> n) & 1 == 0 {
+                        -field.values[period]
+                    } else {
+                        field.values[period]
+                    }
+                })
+                .sum();
+            period_total - goal
+        })
+        .fold((0_f64, 0_f64), |(diff, err), e| (diff + e.abs(), err + e))
+}
+
+/// Ranks the formulas closest to `goal`, only selections including every
+/// field of `pinned_mask` are searched.
+pub fn find_permutation(
+    fields: &[Item],
+    goal: f64,
+    rank_size: usize,
+    pinned_mask: u32,
+) -> Result<SortedVec<SingleResult>, Error> {
+    search_total(
+        fields,
+        goal,
+        rank_size,
+        pinned_mask,
+        &SearchControl::default(),
+    )
+}
+
+pub(crate) fn search_total(
+    fields: &[Item],
+    goal: f64,
+    rank_size: usize,
+    pinned_mask: u32,
+    control: &SearchControl,
+) -> Result<SortedVec<SingleResult>, Error> {
+    search(
+        fields,
+        rank_size,
+        pinned_mask,
+        control,
+        ("total", &[goal]),
+        |sign, select| {
+            let err = get_total_for_perm(sign, select, fields) - goal;
+            (f64::abs(err), err)
+        },
+    )
+}
+
+/// Ranks every formula by the `(diff, error)` that `measure` gives for its
+/// sign and select masks, failing as cancelled when `control` is. Formulas
+/// past the budget of `control` are skipped.
+///
+/// With checkpoints, selections are searched a chunk of about
+/// `control.chunk_evaluations()` formulas at a time, so that the progress can
+/// be saved between chunks. The checkpoint is told apart by the name of the
+/// metric and the goals `measure` is after.
+fn search<F>(
+    fields: &[Item],
+    rank_size: usize,
+    pinned_mask: u32,
+    control: &SearchControl,
+    (metric, goals): (&str, &[f64]),
+    measure: F,
+) -> Result<SortedVec<SingleResult>, Error>
+where
+    F: Fn(u32, u32) -> (f64, f64) + Sync,
+{
+    let num_fields = fields.len();
+    if num_fields > MAX_FIELDS {
+        return Err(Error::TooManyFields {
+            fields: num_fields,
+            max: MAX_FIELDS,
+        });
+    }
+    let all_fields_mask = (1 << num_fields) - 1;
+    control.notice(format!(
+        "Using {:b} mask to compute permutations on {} fields",
+        all_fields_mask, num_fields
+    ));
+
+    let field_names: Vec<String> = fields.iter().map(|i| i.name.clone()).collect();
+
+    // a shard only searches its own slice of the selections
+    let selections = match control.sharding() {
+        Some(shard) => shard_selections(shard, num_fields, pinned_mask),
+        None => 1..all_fields_mask + 1,
+    };
+    let fingerprint = checkpoint::fingerprint(
+        fields,
+        rank_size,
+        pinned_mask,
+        metric,
+        goals,
+        selections.clone(),
+    );
+
+    let checkpointing = control.checkpointing();
+    let resumed = match checkpointing {
+        Some(checkpointing) => checkpointing.load(fingerprint, &field_names)?,
+        None => None,
+    };
+    let (mut next_select, mut rank) = match resumed {
+        Some((next_select, rank)) => {
+            control.notice(format!("Resuming from selection {:b}", next_select));
+            (next_select, rank)
+        }
+        None => (selections.start, SortedVec::new(rank_size)),
+    };
+
+    // progress counts formulas, selections of more fields have more of them
+    let searched_before = evaluations_before(selections.start, pinned_mask);
+    let num_evaluations = evaluations_before(selections.end, pinned_mask) - searched_before;
+    let mut progress = Progress::new(num_evaluations, control.clone());
+    progress.skip(evaluations_before(next_select, pinned_mask) - searched_before);
+
+    let live = control
+        .is_listened()
+        .then(|| LiveRanking::new(rank.clone(), rank_size, control));
+
+    // without checkpoints there is nothing to stop for between chunks
+    let chunk_evaluations = match checkpointing {
+        Some(_) => control.chunk_evaluations(),
+        None => u64::MAX,
+    };
+    let mut last_saved = Instant::now();
+    while next_select < selections.end {
+        // at least a selection, however many formulas it has
+        let chunk_end = selection_reaching(
+            evaluations_before(next_select, pinned_mask).saturating_add(chunk_evaluations),
+            next_select + 1..selections.end,
+            pinned_mask,
+        );
+        let chunk = (next_select..chunk_end)
+            .into_par_iter()
+            .filter(|permutation_select| permutation_select & pinned_mask == pinned_mask)
+            .flat_map_iter(|permutation_select| {
+                let progress = progress.clone();
+                let num_signs = 1_u64 << permutation_select.count_ones();
+                MaskedPermutation::from(permutation_select)
+                    .zip(0_u64..)
+                    // once cancelled, stopped or out of budget the remaining
+                    // signs are skipped, a batch at a time
+                    .take_while(move |&(_, n)| {
+                        if n % SIGN_BATCH != 0 {
+                            return true;
+                        }
+                        let batch = SIGN_BATCH.min(num_signs - n);
+                        let fits = control.spend(batch);
+                        if fits {
+                            progress.tick(batch);
+                        }
+                        fits
+                    })
+                    .map(move |(permutation_sign, _)| (permutation_select, permutation_sign))
+            })
+            .map(|(permutation_select, permutation_sign)| {
+                let (diff, err) = measure(permutation_sign, permutation_select);
+                SingleResult::new(
+                    field_names.clone(),
+                    permutation_sign,
+                    permutation_select,
+                    all_fields_mask,
+                    diff,
+                    err,
+                )
+            })
+            .fold(
+                // This closure is called once per thread to produce a brand-new accumulator:
+                || SortedVec::new(rank_size),
+                |mut acc, single_result| {
+                    if let Some(live) = &live {
+                        live.offer(&single_result);
+                    }
+                    acc.insert_ordered(single_result);
+                    acc
+                },
+            )
+            .reduce_with(SortedVec::merged)
+            .unwrap_or_else(|| SortedVec::new(rank_size));
+
+        if control.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        if control.stopped_early() {
+            // the chunk may be half done, the checkpoint resumes from its start
+            if let Some(checkpointing) = checkpointing {
+                checkpointing.save(fingerprint, next_select, &rank)?;
+            }
+            return Ok(SortedVec::merged(rank, chunk));
+        }
+        rank = SortedVec::merged(rank, chunk);
+        next_select = chunk_end;
+
+        if let Some(checkpointing) = checkpointing
+            && last_saved.elapsed() >= checkpointing.interval
+        {
+            checkpointing.save(fingerprint, next_select, &rank)?;
+            last_saved = Instant::now();
+        }
+    }
+
+    if let Some(checkpointing) = checkpointing {
+        checkpointing.remove(fingerprint)?;
+    }
+    Ok(rank)
+}
+
+/// The best formulas found so far by every thread of a search, sent to the
+/// listener of its control each time they change.
+struct LiveRanking<'a> {
+    rank: Mutex<SortedVec<SingleResult>>,
+    rank_size: usize,
+    /// Diff of the worst formula kept once the rank is full, as `f64` bits,
+    /// so that most formulas are turned down without taking the lock.
+    worst: AtomicU64,
+    /// Changes made to the rank so far, and the last one sent: events are
+    /// sent out of the rank lock, a late copy must not follow a newer one.
+    changes: AtomicU64,
+    sent: Mutex<u64>,
+    control: &'a SearchControl,
+}
+
+impl<'a> LiveRanking<'a> {
+    fn new(rank: SortedVec<SingleResult>, rank_size: usize, control: &'a SearchControl) -> Self {
+        // a resumed search starts from the formulas of its checkpoint
+        if !rank.data.is_empty() {
+            control.emit(SearchEvent::Results(rank.data.clone()));
+        }
+        let worst = match rank.data.last() {
+            Some(last) if rank.data.len() == rank_size => last.diff,
+            _ => f64::INFINITY,
+        };
+        LiveRanking {
+            rank: Mutex::new(rank),
+            rank_size,
+            worst: AtomicU64::new(worst.to_bits()),
+            changes: AtomicU64::new(0),
+            sent: Mutex::new(0),
+            control,
+        }
+    }
+
+    fn offer(&self, result: &SingleResult) {
+        if result.diff > f64::from_bits(self.worst.load(Ordering::Relaxed)) {
+            return;
+        }
+        let (change, results) = {
+            let mut rank = self.rank.lock().unwrap_or_else(|e| e.into_inner());
+            let key = result.get_key();
+            rank.insert_ordered(result.clone());
+            if !rank.data.iter().any(|r| r.get_key() == key) {
+                return;
+            }
+            if rank.data.len() == self.rank_size
+                && let Some(last) = rank.data.last()
+            {
+                self.worst.store(last.diff.to_bits(), Ordering::Relaxed);
+            }
+            let change = self.changes.fetch_add(1, Ordering::Relaxed) + 1;
+            (change, rank.data.clone())
+        };
+        // a slow listener holds up the threads with news, not the search
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        if change > *sent {
+            *sent = change;
+            self.control.emit(SearchEvent::Results(results));
+        }
+    }
+}
+
+/// Formulas of the selections below `end` that include every field of
+/// `pinned_mask`, a selection of `n` fields has `2^n` formulas.
+fn evaluations_before(end: u32, pinned_mask: u32) -> u64 {
+    let (end, pinned) = (u64::from(end), u64::from(pinned_mask));
+    let mut count = 0_u64;
+    // for each set bit of `end`, the numbers sharing its higher bits with
+    // that one clear and any lower bits: each free lower bit is either clear
+    // or set with both signs
+    for bit in 0..32 {
+        if (end >> bit) & 1 == 0 {
+            continue;
+        }
+        let prefix = (end >> (bit + 1)) << (bit + 1);
+        let lower = (1_u64 << bit) - 1;
+        let pinned_higher = pinned & !lower;
+        if prefix & pinned_higher == pinned_higher {
+            let pinned_lower = (pinned & lower).count_ones();
+            count += (1 << (prefix.count_ones() + pinned_lower)) * 3_u64.pow(bit - pinned_lower);
+        }
+    }
+    // the empty selection is never searched
+    if pinned == 0 && end > 0 {
+        count -= 1;
+    }
+    count
+}
+
+/// The selections searched by `shard`, split so that each shard evaluates
+/// about as many formulas.
+fn shard_selections(shard: Shard, num_fields: usize, pinned_mask: u32) -> Range<u32> {
+    let end = 1_u32 << num_fields;
+    let total = evaluations_before(end, pinned_mask);
+    // the first selection whose formulas fall in the `n`th share
+    let bound = |n: u32| {
+        if n == shard.count {
+            return end;
+        }
+        let share = (u128::from(total) * u128::from(n) / u128::from(shard.count)) as u64;
+        selection_reaching(share, 1..end, pinned_mask)
+    };
+    bound(shard.index - 1)..bound(shard.index)
+}
+
+/// The first of `selections` with at least `evaluations` formulas before
+/// it, their end when there is none.
+fn selection_reaching(evaluations: u64, selections: Range<u32>, pinned_mask: u32) -> u32 {
+    let (mut low, mut high) = (selections.start, selections.end);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if evaluations_before(middle, pinned_mask) < evaluations {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
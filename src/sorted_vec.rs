@@ -1,9 +1,11 @@
 use std::fmt::Display;
 use std::fmt::Debug;
 
+use serde::{Deserialize, Serialize};
 
 
-#[derive(Debug, Clone)]
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SortedVec<T>
 where
     T: Ord,
@@ -42,12 +44,11 @@ where
     }
 
     pub fn insert_ordered(&mut self, item: T) {
-        if self.data.len() == self.size_limit {
-          if let Some(last) = self.data.last() {
-            if item > *last {
-              return
-            }
-          }
+        if self.data.len() == self.size_limit
+            && let Some(last) = self.data.last()
+            && item > *last
+        {
+            return;
         }
 
         match self.data.binary_search(&item) {
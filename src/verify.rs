@@ -0,0 +1,94 @@
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::item::Item;
+
+// source named by formula errors
+const FORMULA: &str = "formula";
+
+/// Reads a formula written like the pretty formula, `+ AAAAA - BBBBB`, as
+/// field names with their sign. The sign of the first field may be omitted
+/// and signs may stick to the names, as in `AAAAA -BBBBB`.
+pub fn parse_formula(formula: &str) -> Result<Vec<(i8, String)>, Error> {
+    let mut terms: Vec<(i8, String)> = Vec::new();
+    let mut sign: Option<i8> = None;
+
+    for token in formula.split_whitespace() {
+        let (token_sign, name) = match token.chars().next() {
+            Some('+') => (Some(1), &token[1..]),
+            Some('-') => (Some(-1), &token[1..]),
+            _ => (None, token),
+        };
+        if token_sign.is_some() && sign.is_some() {
+            return Err(Error::parse(FORMULA, None, None, "two signs in a row"));
+        }
+        sign = sign.or(token_sign);
+        if name.is_empty() {
+            continue;
+        }
+        match (sign.take(), terms.last_mut()) {
+            (Some(sign), _) => terms.push((sign, name.to_string())),
+            // names may contain spaces
+            (None, Some((_, last))) => {
+                last.push(' ');
+                last.push_str(name);
+            }
+            (None, None) => terms.push((1, name.to_string())),
+        }
+    }
+
+    if sign.is_some() {
+        return Err(Error::parse(FORMULA, None, None, "sign without a field"));
+    }
+    if terms.is_empty() {
+        return Err(Error::parse(FORMULA, None, None, "no fields"));
+    }
+    Ok(terms)
+}
+
+/// Adds up the fields of a formula over every period.
+pub fn formula_total(terms: &[(i8, String)], fields: &[Item]) -> Result<f64, Error> {
+    terms
+        .iter()
+        .map(|(sign, name)| {
+            let field = fields
+                .iter()
+                .find(|f| &f.name == name)
+                .ok_or_else(|| Error::InvalidOption(format!("field {} not found", name)))?;
+            Ok(f64::from(*sign) * field.total())
+        })
+        .sum()
+}
+
+/// How close a formula gets to the goal.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Verification {
+    pub total: f64,
+    pub goal: f64,
+    pub difference: f64,
+    /// Whether the difference is within the tolerance.
+    pub matches: bool,
+}
+
+/// Totals `formula` over `fields` and compares it with `goal`.
+pub fn verify_formula(
+    formula: &str,
+    fields: &[Item],
+    goal: f64,
+    tolerance: f64,
+) -> Result<Verification, Error> {
+    let terms = parse_formula(formula)?;
+    if fields.iter().any(|i| i.values.iter().any(|v| v.is_nan())) {
+        return Err(Error::InvalidOption(
+            "unknown values can't be totalled".to_string(),
+        ));
+    }
+    let total = formula_total(&terms, fields)?;
+    let difference = total - goal;
+    Ok(Verification {
+        total,
+        goal,
+        difference,
+        matches: difference.abs() <= tolerance,
+    })
+}